@@ -0,0 +1,52 @@
+#!/usr/bin/env sh
+# Runs the queue and direct reply-to suites alternately and prints their durations.
+# Usage: compare.sh [rounds] [run arguments...]
+
+set -eu
+
+bench_dir=$(dirname "$0")
+tester=${TESTER_BIN:-"$bench_dir/../../target/release/cooplan-amqp-api-tester"}
+rounds=${1:-5}
+[ "$#" -gt 0 ] && shift
+
+run_suite() {
+    summary=$("$tester" run "$@" 2>&1 | grep -E '# [0-9]+ passed, .* ms #' | tail -n 1)
+
+    if [ -z "$summary" ]; then
+        echo "no summary found" >&2
+        return 1
+    fi
+
+    echo "$summary" | sed -E 's/.*# ([0-9]+) passed, ([0-9]+) failed, ([0-9]+) errored, ([0-9]+) aborted, ([0-9]+) stray in ([0-9]+) ms #.*/\1 \2 \3 \4 \5 \6/'
+}
+
+queue_total=0
+direct_total=0
+round=1
+
+while [ "$round" -le "$rounds" ]; do
+    for mode in queue direct_reply_to; do
+        result=$(run_suite "$bench_dir/$mode.json" "$@")
+        read -r passed failed errored aborted stray duration <<RESULT
+$result
+RESULT
+
+        if [ "$failed" -ne 0 ] || [ "$errored" -ne 0 ] || [ "$aborted" -ne 0 ] || [ "$stray" -ne 0 ]; then
+            echo "round $round, $mode: $passed passed, $failed failed, $errored errored, $aborted aborted, $stray stray: not comparable" >&2
+            exit 1
+        fi
+
+        echo "round $round, $mode: $duration ms"
+
+        if [ "$mode" = queue ]; then
+            queue_total=$((queue_total + duration))
+        else
+            direct_total=$((direct_total + duration))
+        fi
+    done
+
+    round=$((round + 1))
+done
+
+echo "queue: $((queue_total / rounds)) ms on average"
+echo "direct_reply_to: $((direct_total / rounds)) ms on average"
@@ -1,4 +1,6 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::config::amqp_connection_manager_config::AmqpConnectionManagerConfig;
 use lapin::{Channel, Connection, ConnectionProperties};
@@ -6,16 +8,66 @@ use tokio::sync::Mutex;
 
 use crate::error::{Error, ErrorKind};
 
+/// Longest time waited between two reconnection attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
 pub struct AmqpConnectionManager {
     config: AmqpConnectionManagerConfig,
-    connections: Arc<Mutex<Vec<Connection>>>,
+    connections: Arc<Mutex<Vec<MonitoredConnection>>>,
+}
+
+/// Connection along with its health, which its error handler marks as lost
+/// as soon as the connection fails.
+struct MonitoredConnection {
+    connection: Connection,
+    healthy: Arc<AtomicBool>,
+}
+
+impl MonitoredConnection {
+    fn new(connection: Connection) -> MonitoredConnection {
+        let healthy = Arc::new(AtomicBool::new(true));
+        let error_healthy = healthy.clone();
+
+        connection.on_error(move |error| {
+            log::error!("amqp connection failed: {}", error);
+            error_healthy.store(false, Ordering::Release);
+        });
+
+        MonitoredConnection {
+            connection,
+            healthy,
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Acquire) && self.connection.status().connected()
+    }
+}
+
+/// Channel along with the health of the connection it was created on.
+#[derive(Clone)]
+pub struct AmqpChannel {
+    channel: Channel,
+    connection_healthy: Arc<AtomicBool>,
+}
+
+impl AmqpChannel {
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// Whether neither its connection has failed nor the channel has been closed.
+    pub fn is_healthy(&self) -> bool {
+        self.connection_healthy.load(Ordering::Acquire) && self.channel.status().connected()
+    }
 }
 
 impl AmqpConnectionManager {
+    /// Connects to the broker, retrying the configured times as any later reconnection.
     pub async fn try_new(
         config: AmqpConnectionManagerConfig,
     ) -> Result<AmqpConnectionManager, Error> {
-        let connection = AmqpConnectionManager::amqp_connect(&config).await?;
+        let connection = AmqpConnectionManager::amqp_connect_with_backoff(&config).await?;
 
         Ok(AmqpConnectionManager {
             config,
@@ -42,33 +94,39 @@ impl AmqpConnectionManager {
         Ok(connection)
     }
 
-    pub async fn try_get_channel(&self) -> Result<Channel, Error> {
-        let mut connections = match self.connections.try_lock() {
-            Ok(connections) => connections,
-            Err(error) => {
-                return Err(Error::new(
-                    ErrorKind::InternalFailure,
-                    format!("failed to lock connections: {}", error),
-                ))
+    /// Creates a channel on any of the open connections, dropping the ones which were lost,
+    /// and connects again if none of them can create it.
+    pub async fn try_get_channel(&self) -> Result<AmqpChannel, Error> {
+        let mut connections = self.connections.lock().await;
+
+        connections.retain(|connection| {
+            let healthy = connection.is_healthy();
+
+            if !healthy {
+                log::warn!("amqp connection lost: dropping it");
             }
-        };
+
+            healthy
+        });
 
         for connection in connections.as_slice() {
-            match connection.create_channel().await {
-                Ok(channel) => return Ok(channel),
+            match connection.connection.create_channel().await {
+                Ok(channel) => {
+                    return Ok(AmqpChannel {
+                        channel,
+                        connection_healthy: connection.healthy.clone(),
+                    })
+                }
                 Err(error) => {
                     if error != lapin::Error::ChannelsLimitReached {
-                        return Err(Error::new(
-                            ErrorKind::ApiConnectionFailure,
-                            format!("failed to create channel: {}", error),
-                        ));
+                        log::warn!("failed to create channel: {}", error);
                     }
                 }
             }
         }
 
-        let connection = AmqpConnectionManager::amqp_connect(&self.config).await?;
-        let channel = match connection.create_channel().await {
+        let connection = AmqpConnectionManager::amqp_connect_with_backoff(&self.config).await?;
+        let channel = match connection.connection.create_channel().await {
             Ok(channel) => channel,
             Err(error) => {
                 return Err(Error::new(
@@ -78,7 +136,41 @@ impl AmqpConnectionManager {
             }
         };
 
+        let amqp_channel = AmqpChannel {
+            channel,
+            connection_healthy: connection.healthy.clone(),
+        };
+
         connections.push(connection);
-        Ok(channel)
+        Ok(amqp_channel)
+    }
+
+    /// Connects to the broker, retrying the configured times and doubling the time waited
+    /// between attempts up to a maximum.
+    async fn amqp_connect_with_backoff(
+        config: &AmqpConnectionManagerConfig,
+    ) -> Result<MonitoredConnection, Error> {
+        let mut backoff = std::cmp::min(config.reconnect_backoff(), MAX_RECONNECT_BACKOFF);
+        let mut attempt = 0;
+
+        loop {
+            match AmqpConnectionManager::amqp_connect(config).await {
+                Ok(connection) => return Ok(MonitoredConnection::new(connection)),
+                Err(error) if attempt < config.reconnect_attempts() => {
+                    attempt += 1;
+                    log::warn!(
+                        "{}: reconnecting in {} ms ({}/{})",
+                        error,
+                        backoff.as_millis(),
+                        attempt,
+                        config.reconnect_attempts()
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                    backoff = std::cmp::min(backoff.saturating_mul(2), MAX_RECONNECT_BACKOFF);
+                }
+                Err(error) => return Err(error),
+            }
+        }
     }
 }
@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+const CONFIG_FILE_ENV: &str = "TESTER_CONFIG";
+const CONFIG_PROFILE_ENV: &str = "TESTER_PROFILE";
+const SUITE_FILES_ENV: &str = "SUITE_FILES";
+
+/// Command-line interface of the tester.
+#[derive(Parser)]
+#[command(
+    name = "cooplan-amqp-api-tester",
+    version,
+    about = "Tests AMQP APIs through suites of requests and assertions."
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Retrieves a token and runs the given test suites against the API.
+    Run {
+        #[command(flatten)]
+        token: TokenArgs,
+        #[command(flatten)]
+        suites: SuiteArgs,
+    },
+    /// Checks that the given test suites are ready to be run and prints the tests that would run.
+    /// Neither a token is retrieved nor a connection to the API is opened.
+    #[command(visible_alias = "plan")]
+    Validate {
+        #[command(flatten)]
+        suites: SuiteArgs,
+    },
+    /// Lists the tests contained within the given test suites.
+    List {
+        #[command(flatten)]
+        suites: SuiteArgs,
+    },
+    /// Retrieves a token and prints it into the standard output.
+    Token {
+        #[command(flatten)]
+        token: TokenArgs,
+    },
+    /// Prints the resolved configuration, with secrets redacted.
+    Config {
+        #[command(flatten)]
+        token: TokenArgs,
+    },
+}
+
+/// Settings which override the ones coming from the configuration file and the environment.
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// Configuration file. './amqp-api-tester.toml' is read if it exists and none is given.
+    #[arg(long, global = true, env = CONFIG_FILE_ENV)]
+    pub config: Option<PathBuf>,
+    /// Profile of the configuration file whose settings override the base ones.
+    #[arg(long, global = true, env = CONFIG_PROFILE_ENV)]
+    pub profile: Option<String>,
+    /// URI of the AMQP broker hosting the API.
+    #[arg(long, global = true)]
+    pub amqp_uri: Option<String>,
+    /// Times connecting to the broker is retried, both on startup and once a connection is lost.
+    #[arg(long, global = true)]
+    pub amqp_reconnect_attempts: Option<u64>,
+    /// Time in milliseconds waited before the first reconnection attempt,
+    /// doubled after each failed one.
+    #[arg(long, global = true)]
+    pub amqp_reconnect_backoff_ms: Option<u64>,
+    /// Python 3 interpreter running the assertion scripts with the 'py' extension,
+    /// even if they have a shebang, and the scripts whose runner cannot be told otherwise.
+    #[arg(long, global = true)]
+    pub python_bin: Option<String>,
+    /// Directory containing a folder of assertion scripts per suite.
+    /// Scripts are otherwise resolved relative to their suite file.
+    #[arg(long, global = true)]
+    pub scripts_root: Option<PathBuf>,
+    /// Time in milliseconds an assertion script may run for before being killed,
+    /// or stopped for Rhai scripts run in-process.
+    #[arg(long, global = true)]
+    pub script_timeout_ms: Option<u64>,
+    /// CPU time limit in seconds of assertion script processes, not applied to Rhai scripts.
+    #[arg(long, global = true)]
+    pub script_cpu_limit_secs: Option<u64>,
+    /// Memory limit in megabytes of assertion script processes, not applied to Rhai scripts.
+    #[arg(long, global = true)]
+    pub script_memory_limit_mb: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct TokenArgs {
+    /// URI to which the token request is sent.
+    #[arg(long = "token-uri")]
+    pub uri: Option<String>,
+    /// JSON body of the token request.
+    #[arg(long = "token-body")]
+    pub body: Option<String>,
+}
+
+#[derive(Args)]
+pub struct SuiteArgs {
+    /// Test suite files, directories containing them or glob patterns such as 'suites/**/*.json'.
+    /// The environment variable accepts a colon-separated list.
+    #[arg(required = true, env = SUITE_FILES_ENV, value_delimiter = ':')]
+    pub files: Vec<String>,
+    /// Only keeps the suites and tests whose name matches any of the given glob patterns.
+    /// Patterns are matched against the suite name, the test name and '<suite>/<test>'.
+    #[arg(long = "filter", value_name = "PATTERN")]
+    pub filters: Vec<String>,
+    /// Removes the suites and tests whose name matches any of the given glob patterns.
+    #[arg(long = "skip", value_name = "PATTERN")]
+    pub skips: Vec<String>,
+    /// Comma-separated tags, e.g. 'smoke,!slow': keeps tests having any of the given tags.
+    /// Tags prefixed by '!' exclude every test having them.
+    #[arg(long, value_delimiter = ',')]
+    pub tags: Vec<String>,
+}
@@ -1,12 +1,17 @@
-use lapin::options::{BasicConsumeOptions, BasicPublishOptions};
+use lapin::options::BasicConsumeOptions;
 use lapin::types::FieldTable;
 use serde::{Deserialize, Serialize};
+use crate::config::amqp_publish_options::AmqpPublishOptions;
 use crate::config::amqp_queue::AmqpQueue;
 
 #[derive(Deserialize, Serialize)]
 pub struct Amqp {
     queue: AmqpQueue,
-    publish_options: Option<BasicPublishOptions>,
+    /// Exchange which requests are published to, the default exchange if none is given.
+    exchange: Option<String>,
+    /// Routing key of the requests, the queue name if none is given.
+    routing_key: Option<String>,
+    publish_options: Option<AmqpPublishOptions>,
     publish_arguments: Option<FieldTable>,
     consume_options: Option<BasicConsumeOptions>,
     consume_arguments: Option<FieldTable>
@@ -17,11 +22,15 @@ impl Amqp {
         &self.queue
     }
 
-    pub fn mut_queue(&mut self) -> &mut AmqpQueue {
-        &mut self.queue
+    pub fn exchange(&self) -> Option<&str> {
+        self.exchange.as_deref()
     }
 
-    pub fn publish_options(&self) -> &Option<BasicPublishOptions> {
+    pub fn routing_key(&self) -> Option<&str> {
+        self.routing_key.as_deref()
+    }
+
+    pub fn publish_options(&self) -> &Option<AmqpPublishOptions> {
         &self.publish_options
     }
 
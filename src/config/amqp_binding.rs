@@ -0,0 +1,46 @@
+use lapin::options::QueueBindOptions;
+use lapin::types::FieldTable;
+use serde::{Deserialize, Serialize};
+
+/// Binding of a queue to an exchange, declared before a suite is run.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AmqpBinding {
+    queue: String,
+    exchange: String,
+    #[serde(default)]
+    routing_key: String,
+    #[serde(default)]
+    bind_options: QueueBindOptions,
+    /// Arguments of the binding, which headers exchanges match against.
+    #[serde(default)]
+    bind_arguments: FieldTable,
+    /// Whether the queue is unbound once the suite has been run.
+    #[serde(default)]
+    delete_after_run: bool,
+}
+
+impl AmqpBinding {
+    pub fn queue(&self) -> &str {
+        self.queue.as_str()
+    }
+
+    pub fn exchange(&self) -> &str {
+        self.exchange.as_str()
+    }
+
+    pub fn routing_key(&self) -> &str {
+        self.routing_key.as_str()
+    }
+
+    pub fn bind_options(&self) -> &QueueBindOptions {
+        &self.bind_options
+    }
+
+    pub fn bind_arguments(&self) -> &FieldTable {
+        &self.bind_arguments
+    }
+
+    pub fn delete_after_run(&self) -> bool {
+        self.delete_after_run
+    }
+}
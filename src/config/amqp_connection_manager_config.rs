@@ -1,32 +1,48 @@
+use std::time::Duration;
 
-use crate::error::{Error, ErrorKind};
+use crate::config::tester_config::TesterConfig;
+use crate::error::Error;
 
 pub struct AmqpConnectionManagerConfig {
     connection_uri: String,
+    reconnect_attempts: u64,
+    reconnect_backoff: Duration,
 }
 
 impl AmqpConnectionManagerConfig {
-    pub fn new(connection_uri: String) -> AmqpConnectionManagerConfig {
-        AmqpConnectionManagerConfig { connection_uri }
+    pub fn new(
+        connection_uri: String,
+        reconnect_attempts: u64,
+        reconnect_backoff: Duration,
+    ) -> AmqpConnectionManagerConfig {
+        AmqpConnectionManagerConfig {
+            connection_uri,
+            reconnect_attempts,
+            reconnect_backoff,
+        }
     }
 
     pub fn connection_uri(&self) -> String {
         self.connection_uri.clone()
     }
-}
 
-const AMQP_API_CONNECTION_URI: &str = "AMQP_API_CONNECTION_URI";
+    pub fn reconnect_attempts(&self) -> u64 {
+        self.reconnect_attempts
+    }
 
-pub fn try_generate_config() -> Result<AmqpConnectionManagerConfig, Error> {
-    let connection_uri = match std::env::var(AMQP_API_CONNECTION_URI) {
-        Ok(connection_uri) => connection_uri,
-        Err(error) => {
-            return Err(Error::new(
-                ErrorKind::InternalFailure,
-                format!("failed to read environment variable: {}", error),
-            ));
-        }
-    };
+    pub fn reconnect_backoff(&self) -> Duration {
+        self.reconnect_backoff
+    }
+}
+
+pub fn try_generate_config(
+    tester_config: &TesterConfig,
+) -> Result<AmqpConnectionManagerConfig, Error> {
+    let connection_uri = tester_config.try_amqp_connection_uri()?.to_string();
 
-    Ok(AmqpConnectionManagerConfig::new(connection_uri))
+    Ok(AmqpConnectionManagerConfig::new(
+        connection_uri,
+        tester_config.amqp_reconnect_attempts(),
+        tester_config.amqp_reconnect_backoff(),
+    ))
 }
@@ -0,0 +1,40 @@
+use lapin::options::ExchangeDeclareOptions;
+use lapin::types::FieldTable;
+use lapin::ExchangeKind;
+use serde::{Deserialize, Serialize};
+
+/// Exchange declared before a suite is run.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AmqpExchange {
+    name: String,
+    kind: ExchangeKind,
+    #[serde(default)]
+    declare_options: ExchangeDeclareOptions,
+    #[serde(default)]
+    declare_arguments: FieldTable,
+    /// Whether the exchange is deleted once the suite has been run.
+    #[serde(default)]
+    delete_after_run: bool,
+}
+
+impl AmqpExchange {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn kind(&self) -> &ExchangeKind {
+        &self.kind
+    }
+
+    pub fn declare_options(&self) -> &ExchangeDeclareOptions {
+        &self.declare_options
+    }
+
+    pub fn declare_arguments(&self) -> &FieldTable {
+        &self.declare_arguments
+    }
+
+    pub fn delete_after_run(&self) -> bool {
+        self.delete_after_run
+    }
+}
@@ -9,7 +9,10 @@ use super::amqp::Amqp;
 
 #[derive(Clone)]
 pub struct AmqpInstanceConfig {
+    exchange: String,
+    routing_key: Option<String>,
     publish_options: BasicPublishOptions,
+    #[allow(dead_code)]
     publish_arguments: FieldTable,
     consume_options: BasicConsumeOptions,
     consume_arguments: FieldTable,
@@ -17,12 +20,16 @@ pub struct AmqpInstanceConfig {
 
 impl AmqpInstanceConfig {
     pub fn new(
+        exchange: String,
+        routing_key: Option<String>,
         publish_options: BasicPublishOptions,
         publish_arguments: FieldTable,
         consume_options: BasicConsumeOptions,
         consume_arguments: FieldTable,
     ) -> AmqpInstanceConfig {
         AmqpInstanceConfig {
+            exchange,
+            routing_key,
             publish_options,
             publish_arguments,
             consume_options,
@@ -30,10 +37,21 @@ impl AmqpInstanceConfig {
         }
     }
 
+    /// Exchange which requests are published to.
+    pub fn exchange(&self) -> &str {
+        self.exchange.as_str()
+    }
+
+    /// Routing key of the requests, if other than the name of the request queue.
+    pub fn routing_key(&self) -> Option<&str> {
+        self.routing_key.as_deref()
+    }
+
     pub fn publish_options(&self) -> &BasicPublishOptions {
         &self.publish_options
     }
 
+    #[allow(dead_code)]
     pub fn publish_arguments(&self) -> &FieldTable {
         &self.publish_arguments
     }
@@ -52,7 +70,7 @@ pub fn try_get_from_request_and_reply_amqp(
     reply_amqp: &Amqp,
 ) -> Result<AmqpInstanceConfig, Error> {
     let publish_options = match request_amqp.publish_options() {
-        Some(publish_options) => publish_options.clone(),
+        Some(publish_options) => BasicPublishOptions::from(*publish_options),
         None => {
             return Err(Error::new(
                 ErrorKind::InternalFailure,
@@ -72,7 +90,7 @@ pub fn try_get_from_request_and_reply_amqp(
     };
 
     let consume_options = match reply_amqp.consume_options() {
-        Some(consume_options) => consume_options.clone(),
+        Some(consume_options) => *consume_options,
         None => {
             return Err(Error::new(
                 ErrorKind::InternalFailure,
@@ -92,6 +110,8 @@ pub fn try_get_from_request_and_reply_amqp(
     };
 
     Ok(AmqpInstanceConfig::new(
+        request_amqp.exchange().unwrap_or_default().to_string(),
+        request_amqp.routing_key().map(String::from),
         publish_options,
         publish_arguments,
         consume_options,
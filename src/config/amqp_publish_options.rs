@@ -0,0 +1,26 @@
+use lapin::options::BasicPublishOptions;
+use serde::{Deserialize, Serialize};
+
+fn default_mandatory() -> bool {
+    true
+}
+
+/// Options which requests are published with.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct AmqpPublishOptions {
+    /// Whether the broker returns requests it cannot route to any queue, so they are reported
+    /// as unroutable instead of timing out. Requests are mandatory unless set to false.
+    #[serde(default = "default_mandatory")]
+    mandatory: bool,
+    #[serde(default)]
+    immediate: bool,
+}
+
+impl From<AmqpPublishOptions> for BasicPublishOptions {
+    fn from(publish_options: AmqpPublishOptions) -> BasicPublishOptions {
+        BasicPublishOptions {
+            mandatory: publish_options.mandatory,
+            immediate: publish_options.immediate,
+        }
+    }
+}
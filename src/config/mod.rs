@@ -1,4 +1,8 @@
 pub mod amqp;
+pub mod amqp_binding;
 pub mod amqp_connection_manager_config;
+pub mod amqp_exchange;
 pub mod amqp_instance_config;
+pub mod amqp_publish_options;
 pub mod amqp_queue;
+pub mod tester_config;
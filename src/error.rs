@@ -5,6 +5,14 @@ pub enum ErrorKind {
     ApiConnectionFailure,
     InternalFailure,
     TestAssertFailure,
+    /// No reply was received before the test's reply timeout expired.
+    ReplyTimeout,
+    /// The assert script did not end before its timeout expired.
+    ScriptTimeout,
+    /// The broker returned or refused the request, so it never reached the API.
+    RequestRejected,
+    /// The run was interrupted before the test could finish.
+    Aborted,
 }
 
 #[derive(Debug, Clone)]
@@ -1,13 +1,20 @@
 use crate::amqp_connection_manager::AmqpConnectionManager;
+use crate::cli::{Cli, Command, ConfigArgs, SuiteArgs, TokenArgs};
+use crate::config::tester_config::{self, TesterConfig, TesterConfigLayer};
+use crate::testing::suite::Suite;
 use crate::testing::suite_result::SuiteResult;
 use crate::testing::suite_runner::SuiteRunner;
-use crate::testing::{suite_reader, suite_result_output};
+use crate::testing::test_filter::TestFilter;
+use crate::testing::{suite_discovery, suite_planner, suite_reader, suite_result_output};
+use clap::Parser;
 use std::{
     io::{Error, ErrorKind},
     sync::Arc,
 };
+use tokio_util::sync::CancellationToken;
 
 mod amqp_connection_manager;
+mod cli;
 mod config;
 mod error;
 mod testing;
@@ -15,92 +22,184 @@ mod token_retriever;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+
     match simple_logger::init() {
         Ok(_) => (),
         Err(error) => {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("failed to initialize logger: {}", error),
-            ));
+            return Err(Error::other(format!(
+                "failed to initialize logger: {}",
+                error
+            )));
         }
     }
 
-    let arguments: Vec<String> = std::env::args().collect();
+    let token_args = match &cli.command {
+        Command::Run { token, .. } | Command::Token { token } | Command::Config { token } => {
+            Some(token)
+        }
+        Command::Validate { .. } | Command::List { .. } => None,
+    };
 
-    if arguments.len() != 4 {
-        return Err(Error::new(ErrorKind::InvalidInput, "no test file provided"));
-    }
+    let tester_config = Arc::new(generate_config(&cli.config, token_args)?);
 
-    log::info!("# executing tester with the following arguments #");
-    for argument in arguments.as_slice() {
-        log::info!("\t-> {}", argument);
-    }
+    match cli.command {
+        Command::Run { suites, .. } => {
+            let exit_code = run(tester_config, suites).await?;
+            std::process::exit(exit_code);
+        }
+        Command::Validate { suites } => validate(&tester_config, suites).await,
+        Command::List { suites } => list(suites).await,
+        Command::Token { .. } => {
+            let token = get_token(&tester_config).await?;
+            println!("{}", token);
 
-    let token_request_uri = match arguments.get(1) {
-        Some(token_request_uri) => token_request_uri.to_string(),
-        None => {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "no token request uri provided",
-            ));
+            Ok(())
         }
+        Command::Config { .. } => match tester_config.try_to_redacted_string() {
+            Ok(config) => {
+                println!("{}", config);
+
+                Ok(())
+            }
+            Err(error) => Err(Error::other(format!(
+                "failed to print configuration: {}",
+                error
+            ))),
+        },
+    }
+}
+
+fn generate_config(
+    config_args: &ConfigArgs,
+    token_args: Option<&TokenArgs>,
+) -> Result<TesterConfig, Error> {
+    let cli_layer = TesterConfigLayer {
+        amqp_connection_uri: config_args.amqp_uri.clone(),
+        amqp_reconnect_attempts: config_args.amqp_reconnect_attempts,
+        amqp_reconnect_backoff_ms: config_args.amqp_reconnect_backoff_ms,
+        token_request_uri: token_args.and_then(|token_args| token_args.uri.clone()),
+        token_request_body: token_args.and_then(|token_args| token_args.body.clone()),
+        python_bin: config_args.python_bin.clone(),
+        scripts_root: config_args.scripts_root.clone(),
+        script_timeout_ms: config_args.script_timeout_ms,
+        script_cpu_limit_secs: config_args.script_cpu_limit_secs,
+        script_memory_limit_mb: config_args.script_memory_limit_mb,
     };
 
-    let token_request_body = match arguments.get(2) {
-        Some(token_request_body) => token_request_body.to_string(),
-        None => {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "no token request body provided",
-            ));
-        }
+    match tester_config::try_generate_config(
+        config_args.config.as_deref(),
+        config_args.profile.as_deref(),
+        cli_layer,
+    ) {
+        Ok(tester_config) => Ok(tester_config),
+        Err(error) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("failed to generate configuration: {}", error),
+        )),
+    }
+}
+
+async fn get_token(tester_config: &TesterConfig) -> Result<String, Error> {
+    let token_request_uri = match tester_config.try_token_request_uri() {
+        Ok(token_request_uri) => token_request_uri,
+        Err(error) => return Err(Error::new(ErrorKind::InvalidInput, error.message())),
     };
 
-    let token = match token_retriever::try_get_token(token_request_uri, token_request_body).await {
-        Ok(token) => token,
-        Err(error) => {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("failed to get token: {}", error),
-            ));
-        }
+    let token_request_body = match tester_config.try_token_request_body() {
+        Ok(token_request_body) => token_request_body,
+        Err(error) => return Err(Error::new(ErrorKind::InvalidInput, error.message())),
     };
 
-    log::info!("obtained token correctly!");
+    match token_retriever::try_get_token(
+        token_request_uri.to_string(),
+        token_request_body.to_string(),
+    )
+    .await
+    {
+        Ok(token) => Ok(token),
+        Err(error) => Err(Error::other(format!("failed to get token: {}", error))),
+    }
+}
 
-    let test_suite_files = match arguments.get(3) {
-        Some(test_suite_files) => test_suite_files.split(':').collect::<Vec<&str>>(),
-        None => {
+async fn read_suites(suite_args: &SuiteArgs, token: Option<&str>) -> Result<Vec<Suite>, Error> {
+    let files = match suite_discovery::discover(suite_args.files.as_slice()) {
+        Ok(files) => files,
+        Err(error) => {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
-                "no test suite files provided",
+                format!("failed to find test files: {}", error),
             ));
         }
     };
 
-    let test_suites = match suite_reader::read(test_suite_files.as_slice(), token.as_str()).await {
-        Ok(tests) => tests,
+    let test_filter = match TestFilter::try_new(
+        suite_args.filters.as_slice(),
+        suite_args.skips.as_slice(),
+        suite_args.tags.as_slice(),
+    ) {
+        Ok(test_filter) => test_filter,
         Err(error) => {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
-                format!("failed to read test files: {}", error),
+                format!("failed to create test filter: {}", error),
             ));
         }
     };
 
+    match suite_reader::read(files.as_slice(), token).await {
+        Ok(suites) => Ok(test_filter.apply(suites)),
+        Err(error) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("failed to read test files: {}", error),
+        )),
+    }
+}
+
+async fn validate(tester_config: &TesterConfig, suite_args: SuiteArgs) -> Result<(), Error> {
+    let test_suites = read_suites(&suite_args, None).await?;
+
+    match suite_planner::plan(tester_config, test_suites.as_slice()) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("test suites are not valid: {}", error),
+        )),
+    }
+}
+
+async fn list(suite_args: SuiteArgs) -> Result<(), Error> {
+    let test_suites = read_suites(&suite_args, None).await?;
+
+    for test_suite in &test_suites {
+        println!("{}", test_suite.name());
+
+        for test in test_suite.tests() {
+            println!("\t{}", test.name());
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs every test suite and returns the exit code the process should end with.
+async fn run(tester_config: Arc<TesterConfig>, suite_args: SuiteArgs) -> Result<i32, Error> {
+    let token = get_token(&tester_config).await?;
+
+    log::info!("obtained token correctly!");
+
+    let test_suites = read_suites(&suite_args, Some(token.as_str())).await?;
+
     let test_suites_length = test_suites.len();
 
     let amqp_connection_manager_config =
-        match config::amqp_connection_manager_config::try_generate_config() {
+        match config::amqp_connection_manager_config::try_generate_config(&tester_config) {
             Ok(amqp_connection_manager_config) => amqp_connection_manager_config,
             Err(error) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!(
-                        "failed to generate amqp connection manager config: {}",
-                        error
-                    ),
-                ));
+                return Err(Error::other(format!(
+                    "failed to generate amqp connection manager config: {}",
+                    error
+                )));
             }
         };
 
@@ -108,57 +207,109 @@ async fn main() -> Result<(), Error> {
         match AmqpConnectionManager::try_new(amqp_connection_manager_config).await {
             Ok(amqp_connection_manager) => Arc::new(amqp_connection_manager),
             Err(error) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("failed to create amqp connection manager: {}", error),
-                ));
+                return Err(Error::other(format!(
+                    "failed to create amqp connection manager: {}",
+                    error
+                )));
             }
         };
 
+    let cancellation_token = CancellationToken::new();
+    tokio::spawn(cancel_on_shutdown_signal(cancellation_token.clone()));
+
     let (result_sender, mut result_receiver) = tokio::sync::mpsc::channel::<SuiteResult>(4096);
 
     for test_suite in test_suites {
-        let mut test_runner =
-            SuiteRunner::new(amqp_connection_manager.clone(), result_sender.clone());
+        let mut test_runner = SuiteRunner::new(
+            amqp_connection_manager.clone(),
+            result_sender.clone(),
+            tester_config.clone(),
+            cancellation_token.clone(),
+        );
         let test_name = test_suite.name().to_string();
 
         tokio::spawn(async move {
-            match test_runner.execute(test_suite).await {
-                Ok(()) => (),
-                Err(error) => {
-                    log::error!("failed to run test suite '{}': {}", test_name, error);
-                    std::process::exit(1);
-                }
+            if let Err(error) = test_runner.execute(test_suite).await {
+                log::error!("failed to run test suite '{}': {}", test_name, error);
             }
         });
     }
 
+    // only the suite runners keep a sender, so the receiver ends if any of them stops
+    // without sending its result instead of waiting forever.
+    drop(result_sender);
+
     let mut exit_code = 0;
     let mut test_suite_count = 0;
 
-    loop {
-        match result_receiver.recv().await {
-            Some(test_suite_result) => {
-                if test_suite_result.has_any_test_failed() {
-                    exit_code = 1;
-                }
-
-                match suite_result_output::output(test_suite_result) {
-                    Ok(()) => (),
-                    Err(error) => {
-                        log::error!("failed to output test suite result: {}", error);
-                        std::process::exit(1);
-                    }
-                }
-                test_suite_count += 1;
+    while test_suite_count < test_suites_length {
+        let test_suite_result = match result_receiver.recv().await {
+            Some(test_suite_result) => test_suite_result,
+            None => {
+                log::error!(
+                    "{} test suite(s) ended without reporting their results",
+                    test_suites_length - test_suite_count
+                );
+                exit_code = 1;
+                break;
             }
-            None => (),
         };
 
-        if test_suite_count >= test_suites_length {
-            break;
+        if test_suite_result.has_failed() {
+            exit_code = 1;
+        }
+
+        if let Err(error) = suite_result_output::output(test_suite_result) {
+            log::error!("failed to output test suite result: {}", error);
+            exit_code = 1;
         }
+
+        test_suite_count += 1;
     }
 
-    std::process::exit(exit_code);
+    Ok(exit_code)
+}
+
+/// Cancels the run once an interruption or termination signal is received, so the suites
+/// can clean their reply queues and report the results collected so far.
+/// A second signal ends the process straight away.
+async fn cancel_on_shutdown_signal(cancellation_token: CancellationToken) {
+    wait_for_shutdown_signal().await;
+    log::warn!("shutdown signal received: aborting the run, send it again to force exit");
+    cancellation_token.cancel();
+
+    wait_for_shutdown_signal().await;
+    log::error!("shutdown forced");
+    std::process::exit(130);
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate_signal = match signal(SignalKind::terminate()) {
+        Ok(terminate_signal) => terminate_signal,
+        Err(error) => {
+            log::error!("failed to listen for termination signal: {}", error);
+            wait_for_interruption_signal().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = wait_for_interruption_signal() => (),
+        _ = terminate_signal.recv() => (),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() {
+    wait_for_interruption_signal().await;
+}
+
+async fn wait_for_interruption_signal() {
+    if let Err(error) = tokio::signal::ctrl_c().await {
+        log::error!("failed to listen for interruption signal: {}", error);
+        std::future::pending::<()>().await;
+    }
 }
@@ -1,53 +1,925 @@
-use std::sync::Arc;
-use tokio::process::Command;
+use crate::config::tester_config::TesterConfig;
 use crate::error::{Error, ErrorKind};
+use crate::testing::assert_worker::AssertWorker;
+use crate::testing::reply::Reply;
+use crate::testing::rhai_assert_engine::{RhaiAssertEngine, RHAI_SCRIPT_EXTENSION};
+use crate::testing::suite::Suite;
+use crate::testing::test::Test;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Version of the JSON envelope given to scripts, increased whenever its layout changes.
+pub const ENVELOPE_VERSION: u64 = 1;
+
+const CORRELATION_ID_ENV: &str = "AMQP_TESTER_CORRELATION_ID";
+const TEST_ENV: &str = "AMQP_TESTER_TEST";
+const SUITE_ENV: &str = "AMQP_TESTER_SUITE";
+const LATENCY_MS_ENV: &str = "AMQP_TESTER_LATENCY_MS";
+const CONTENT_TYPE_ENV: &str = "AMQP_TESTER_CONTENT_TYPE";
+const HEADERS_ENV: &str = "AMQP_TESTER_HEADERS";
+const SCRIPT_INPUT_ENV: &str = "AMQP_TESTER_SCRIPT_INPUT";
+
+/// Runner which runs scripts in-process through the embedded Rhai engine.
+const EMBEDDED_RUNNER: &str = "rhai";
+/// Runner which runs scripts directly, as executables.
+const EXECUTABLE_RUNNER: &str = "exec";
+const SHEBANG: &[u8] = b"#!";
+const MAX_SHEBANG_BYTES: usize = 256;
+
+/// Times the assertion worker of a suite is restarted after exiting unexpectedly.
+const MAX_WORKER_RESTARTS: u32 = 3;
+
+/// Maximum amount of bytes kept from each of the output streams of a script.
+const MAX_SCRIPT_OUTPUT_BYTES: usize = 16 * 1024;
+/// Time given to the output of a script to be read once it has ended or been killed,
+/// in case a process it spawned outside of its process group keeps its output open.
+const SCRIPT_OUTPUT_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What an assertion script receives through its standard input.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptInput {
+    /// The reply payload as is, while its metadata is given through environment variables.
+    #[default]
+    Raw,
+    /// A JSON document containing the envelope version, the metadata of the reply,
+    /// its payload decoded as JSON under `body` (null if it is not JSON)
+    /// and its raw payload encoded as base64 under `body_base64`.
+    Envelope,
+}
+
+impl ScriptInput {
+    /// Name of the script input as written in suites, which scripts are given in their environment.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScriptInput::Raw => "raw",
+            ScriptInput::Envelope => "envelope",
+        }
+    }
+}
+
+/// How an assertion script is run.
+#[derive(Debug, PartialEq)]
+enum ScriptRunner {
+    Embedded,
+    Executable,
+    /// Command, along with its arguments, which receives the script path as its last argument.
+    Interpreter(Vec<String>),
+}
+
+impl ScriptRunner {
+    fn from_command(command: &str) -> ScriptRunner {
+        match command.trim() {
+            EMBEDDED_RUNNER => ScriptRunner::Embedded,
+            EXECUTABLE_RUNNER => ScriptRunner::Executable,
+            command => {
+                ScriptRunner::Interpreter(command.split_whitespace().map(String::from).collect())
+            }
+        }
+    }
+}
+
+/// Checks that a runner specified by a suite or a test names a runner.
+pub fn try_validate_runner(runner: &str) -> Result<(), Error> {
+    if runner.trim().is_empty() {
+        return Err(Error::new(ErrorKind::InternalFailure, "runner is blank"));
+    }
 
-const PYTHON_3_BIN_ENV: &str = "PYTHON_3_BIN";
+    Ok(())
+}
 
-const DEFAULT_INTEGRATION_TESTS_PATH: &str = "./integration_tests";
+/// Returns the path of an assertion script within the scripts directory of its suite.
+pub fn script_path(scripts_dir: &Path, script_name: &str) -> PathBuf {
+    scripts_dir.join(script_name)
+}
 
+/// Runs the assertion scripts of a test suite.
+/// The runner of a script is picked, in order, from: the `runner` of its test, the `runner` of its
+/// suite, its shebang line, its file extension, and whether it is executable.
+/// Scripts with the 'py' extension are run by the configured Python interpreter regardless
+/// of their shebang, as are scripts matching none of them.
+/// A runner is either 'rhai', which runs the script in-process, 'exec', which runs the script
+/// directly, or the command of an interpreter such as 'node' or 'bash -e'.
+/// Script processes are killed along with any process they spawned if they do not end
+/// before their timeout, and may be limited in CPU time and memory.
+/// Rhai scripts are stopped once their timeout elapses as well, but the CPU time and memory
+/// limits do not apply to them, since they are run in-process.
+/// If the suite specifies an assertion worker, every reply is asserted by it instead.
 pub struct AssertScriptRunner {
     test_suite_name: Arc<String>,
+    suite_runner: Option<String>,
+    assert_worker_path: Option<PathBuf>,
+    assert_worker: Mutex<WorkerState>,
     python_bin: String,
+    scripts_dir: PathBuf,
+    script_timeout: Duration,
+    script_cpu_limit_secs: Option<u64>,
+    script_memory_limit_mb: Option<u64>,
+    rhai_assert_engine: Arc<RhaiAssertEngine>,
 }
 
 impl AssertScriptRunner {
-    pub fn try_new(test_suite_name: Arc<String>) -> Result<AssertScriptRunner, Error> {
-        let python_bin = match std::env::var(PYTHON_3_BIN_ENV) {
-            Ok(python_bin) => python_bin,
-            Err(error) => return Err(Error::new(ErrorKind::InternalFailure, format!("failed to read python bin environment variable: {}", error)))
-        };
+    pub fn try_new(
+        test_suite: &Suite,
+        tester_config: &TesterConfig,
+    ) -> Result<AssertScriptRunner, Error> {
+        let scripts_dir = test_suite.scripts_dir(tester_config.scripts_root());
 
         Ok(AssertScriptRunner {
-            test_suite_name,
-            python_bin
+            test_suite_name: Arc::new(test_suite.name().to_string()),
+            suite_runner: test_suite.runner().map(String::from),
+            assert_worker_path: test_suite
+                .assert_worker()
+                .map(|assert_worker| script_path(scripts_dir.as_path(), assert_worker)),
+            assert_worker: Mutex::new(WorkerState::default()),
+            python_bin: tester_config.python_bin().to_string(),
+            scripts_dir,
+            script_timeout: tester_config.script_timeout(),
+            script_cpu_limit_secs: tester_config.script_cpu_limit_secs(),
+            script_memory_limit_mb: tester_config.script_memory_limit_mb(),
+            rhai_assert_engine: Arc::new(RhaiAssertEngine::try_new()?),
         })
     }
 
-    pub async fn run_script(&self, script_name: &str, response: Vec<u8>) -> Result<(), Error> {
-        let response = match String::from_utf8(response) {
-            Ok(response) => response,
-            Err(error) => return Err(Error::new(ErrorKind::InternalFailure, format!("failed to decode response: {}", error)))
-        };
+    /// Runs the assertion script of the test against the reply.
+    /// The reply is written into the standard input of the script, as specified by the script input,
+    /// and its metadata is given through environment variables.
+    /// The output of the script is returned along with its result whenever the script could be run.
+    pub async fn run_script(
+        &self,
+        test: &Test,
+        reply: &Reply,
+    ) -> (Result<(), Error>, Option<ScriptOutput>) {
+        let metadata = reply.metadata(self.test_suite_name.as_str(), test.name());
+
+        if let Some(assert_worker_path) = &self.assert_worker_path {
+            let result = self
+                .assert_with_worker(assert_worker_path.as_path(), test, reply, metadata)
+                .await;
+
+            return (result, None);
+        }
 
-        let file_path = format!("{}/{}/{}", DEFAULT_INTEGRATION_TESTS_PATH, self.test_suite_name, script_name);
-        let result = match Command::new(&self.python_bin)
-            .arg(&file_path)
-            .arg(response)
-            .spawn() {
-            Ok(mut process) => process.wait().await,
-            Err(error) => return Err(Error::new(ErrorKind::InternalFailure, format!("failed to spawn process: {}", error)))
+        let script_name = match test.assert_script() {
+            Some(script_name) => script_name,
+            None => return (Ok(()), None),
         };
 
-        match result {
-            Ok(exit_status) => if exit_status.success() {
-                Ok(())
-            } else {
-                return Err(Error::new(ErrorKind::TestAssertFailure, format!("assertion script '{}' failed: '{}'", file_path, exit_status)))
+        let file_path = script_path(self.scripts_dir.as_path(), script_name);
+
+        let runner = self
+            .resolve_runner(test.runner(), file_path.as_path())
+            .await;
+
+        if let ScriptRunner::Embedded = runner {
+            return self
+                .run_embedded_script(file_path, reply.data().to_vec(), metadata, test)
+                .await;
+        }
+
+        let input = match test.script_input() {
+            ScriptInput::Raw => reply.data().to_vec(),
+            ScriptInput::Envelope => match create_envelope(metadata, reply) {
+                Ok(envelope) => envelope,
+                Err(error) => return (Err(error), None),
             },
-            Err(error) => Err(Error::new(ErrorKind::InternalFailure,
-                                         format!("failed to wait for process to end: {}", error),
+        };
+
+        let (execution, output) = self
+            .run_process(file_path.as_path(), runner, test, reply, input)
+            .await;
+
+        let result = match execution {
+            Ok(exit_status) if exit_status.success() => Ok(()),
+            Ok(exit_status) => Err(Error::new(
+                ErrorKind::TestAssertFailure,
+                format!(
+                    "assertion script '{}' failed: '{}'",
+                    file_path.display(),
+                    exit_status
+                ),
             )),
+            Err(error) => Err(error),
+        };
+
+        (result, output)
+    }
+
+    /// Runs the script in-process on a blocking thread, so a long running script
+    /// does not stall the other tests.
+    async fn run_embedded_script(
+        &self,
+        file_path: PathBuf,
+        reply: Vec<u8>,
+        metadata: Map<String, Value>,
+        test: &Test,
+    ) -> (Result<(), Error>, Option<ScriptOutput>) {
+        let rhai_assert_engine = self.rhai_assert_engine.clone();
+        let script_timeout = self.script_timeout(test);
+
+        match tokio::task::spawn_blocking(move || {
+            rhai_assert_engine.run_script(
+                file_path.as_path(),
+                reply.as_slice(),
+                metadata,
+                script_timeout,
+            )
+        })
+        .await
+        {
+            Ok(execution) => execution,
+            Err(error) => (
+                Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to run assertion script: {}", error),
+                )),
+                None,
+            ),
+        }
+    }
+
+    async fn assert_with_worker(
+        &self,
+        assert_worker_path: &Path,
+        test: &Test,
+        reply: &Reply,
+        metadata: Map<String, Value>,
+    ) -> Result<(), Error> {
+        let assert_worker = self.try_get_worker(assert_worker_path).await?;
+
+        let mut request = create_envelope_document(metadata, reply);
+        request.insert("script".to_string(), Value::from(test.assert_script()));
+
+        assert_worker
+            .assert(request, self.script_timeout(test))
+            .await
+    }
+
+    /// Returns the running assertion worker, starting it if it has not been started yet
+    /// and restarting it if it has exited, as long as it has not exited too many times.
+    async fn try_get_worker(&self, assert_worker_path: &Path) -> Result<Arc<AssertWorker>, Error> {
+        let mut worker_state = self.assert_worker.lock().await;
+
+        if let Some(assert_worker) = &worker_state.assert_worker {
+            if !assert_worker.has_exited() {
+                return Ok(assert_worker.clone());
+            }
+
+            if worker_state.restarts >= MAX_WORKER_RESTARTS {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "assertion worker '{}' exited after {} restarts: not restarting it",
+                        assert_worker_path.display(),
+                        worker_state.restarts
+                    ),
+                ));
+            }
+
+            log::warn!(
+                "[{}] assertion worker '{}' exited: restarting it",
+                self.test_suite_name,
+                assert_worker_path.display()
+            );
+
+            assert_worker.stop().await;
+            worker_state.assert_worker = None;
+            worker_state.restarts += 1;
+        }
+
+        let assert_worker = Arc::new(self.try_start_worker(assert_worker_path).await?);
+        worker_state.assert_worker = Some(assert_worker.clone());
+
+        Ok(assert_worker)
+    }
+
+    /// Stops the assertion worker, if any was started, once every test of the suite has ended.
+    pub async fn stop_assert_worker(&self) {
+        let assert_worker = self.assert_worker.lock().await.assert_worker.take();
+
+        if let Some(assert_worker) = assert_worker {
+            assert_worker.stop().await;
+        }
+    }
+
+    async fn try_start_worker(&self, assert_worker_path: &Path) -> Result<AssertWorker, Error> {
+        let runner = self.resolve_runner(None, assert_worker_path).await;
+
+        if let ScriptRunner::Embedded = runner {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!(
+                    "assertion worker '{}' cannot be run in-process",
+                    assert_worker_path.display()
+                ),
+            ));
+        }
+
+        let mut command = create_command(&runner, assert_worker_path);
+        command.env(SUITE_ENV, self.test_suite_name.as_str());
+
+        // the CPU time limit is meant for a single assertion, not for a process running them all
+        self.limit_process(&mut command, None);
+
+        AssertWorker::try_start(command, assert_worker_path, self.test_suite_name.as_str())
+    }
+
+    fn script_timeout(&self, test: &Test) -> Duration {
+        test.script_timeout_ms()
+            .map(Duration::from_millis)
+            .unwrap_or(self.script_timeout)
+    }
+
+    async fn resolve_runner(&self, test_runner: Option<&str>, file_path: &Path) -> ScriptRunner {
+        if let Some(runner) = test_runner.or(self.suite_runner.as_deref()) {
+            return ScriptRunner::from_command(runner);
+        }
+
+        let extension = file_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+
+        // the configured interpreter is kept for Python scripts, whose shebang usually names
+        // whichever interpreter is found first, such as '/usr/bin/env python3'
+        if extension == "py" {
+            return ScriptRunner::Interpreter(vec![self.python_bin.clone()]);
+        }
+
+        // the shebang is more specific than the extension, such as a bash script named '.sh'
+        if let Some(interpreter) = read_shebang(file_path).await {
+            return ScriptRunner::from_command(interpreter.as_str());
+        }
+
+        match extension {
+            RHAI_SCRIPT_EXTENSION => return ScriptRunner::Embedded,
+            "js" | "mjs" | "cjs" => return ScriptRunner::from_command("node"),
+            "sh" => return ScriptRunner::from_command("sh"),
+            "bash" => return ScriptRunner::from_command("bash"),
+            _ => (),
+        }
+
+        if is_executable(file_path).await {
+            return ScriptRunner::Executable;
+        }
+
+        ScriptRunner::Interpreter(vec![self.python_bin.clone()])
+    }
+
+    /// Runs the script as a process, returning its output whenever it could be spawned,
+    /// including the output written before it was killed.
+    async fn run_process(
+        &self,
+        file_path: &Path,
+        runner: ScriptRunner,
+        test: &Test,
+        reply: &Reply,
+        input: Vec<u8>,
+    ) -> (Result<ExitStatus, Error>, Option<ScriptOutput>) {
+        let headers = match serde_json::to_string(reply.headers()) {
+            Ok(headers) => headers,
+            Err(error) => {
+                return (
+                    Err(Error::new(
+                        ErrorKind::InternalFailure,
+                        format!("failed to serialize reply headers: {}", error),
+                    )),
+                    None,
+                )
+            }
+        };
+
+        let mut command = create_command(&runner, file_path);
+
+        command
+            .env(CORRELATION_ID_ENV, reply.correlation_id())
+            .env(TEST_ENV, test.name())
+            .env(SUITE_ENV, self.test_suite_name.as_str())
+            .env(
+                LATENCY_MS_ENV,
+                (reply.latency().as_secs_f64() * 1000.0).to_string(),
+            )
+            .env(CONTENT_TYPE_ENV, reply.content_type().unwrap_or_default())
+            .env(HEADERS_ENV, headers)
+            .env(SCRIPT_INPUT_ENV, test.script_input().as_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        self.limit_process(&mut command, self.script_cpu_limit_secs);
+
+        let mut process = match command.spawn() {
+            Ok(process) => process,
+            Err(error) => {
+                return (
+                    Err(Error::new(
+                        ErrorKind::InternalFailure,
+                        format!(
+                            "failed to spawn assertion script '{}': {}",
+                            file_path.display(),
+                            error
+                        ),
+                    )),
+                    None,
+                )
+            }
+        };
+
+        // the output is read while the input is written, otherwise a script writing
+        // a large output before reading its input would never end. It is read apart from
+        // the script's execution, so what was read is kept even if the script is killed.
+        let stdout_reader = tokio::spawn(read_output(process.stdout.take()));
+        let stderr_reader = tokio::spawn(read_output(process.stderr.take()));
+
+        let script_timeout = self.script_timeout(test);
+
+        let execution = tokio::time::timeout(script_timeout, async {
+            write_input(process.stdin.take(), input).await?;
+
+            match process.wait().await {
+                Ok(exit_status) => Ok(exit_status),
+                Err(error) => Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to wait for process to end: {}", error),
+                )),
+            }
+        })
+        .await;
+
+        let execution = match execution {
+            Ok(Ok(exit_status)) => Ok(exit_status),
+            Ok(Err(error)) => {
+                kill_process(&mut process).await;
+                Err(error)
+            }
+            Err(_) => {
+                kill_process(&mut process).await;
+
+                Err(Error::new(
+                    ErrorKind::ScriptTimeout,
+                    format!(
+                        "assertion script '{}' did not end within {} ms",
+                        file_path.display(),
+                        script_timeout.as_millis()
+                    ),
+                ))
+            }
+        };
+
+        let output = ScriptOutput::new(
+            collect_output(stdout_reader).await,
+            collect_output(stderr_reader).await,
+        );
+
+        (execution, Some(output))
+    }
+
+    /// Places the process into its own process group, so it can be killed along with its children,
+    /// and applies the configured resource limits.
+    #[cfg(unix)]
+    fn limit_process(&self, command: &mut Command, cpu_limit_secs: Option<u64>) {
+        command.process_group(0);
+
+        let memory_limit_bytes = self
+            .script_memory_limit_mb
+            .map(|memory_limit_mb| memory_limit_mb.saturating_mul(1024 * 1024));
+
+        if cpu_limit_secs.is_none() && memory_limit_bytes.is_none() {
+            return;
+        }
+
+        // SAFETY: only async-signal-safe functions are called between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                let set_resource_limit = |resource, limit: u64| {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit as libc::rlim_t,
+                        rlim_max: limit as libc::rlim_t,
+                    };
+
+                    match libc::setrlimit(resource, &limit) {
+                        0 => Ok(()),
+                        _ => Err(std::io::Error::last_os_error()),
+                    }
+                };
+
+                if let Some(cpu_limit_secs) = cpu_limit_secs {
+                    set_resource_limit(libc::RLIMIT_CPU, cpu_limit_secs)?;
+                }
+
+                if let Some(memory_limit_bytes) = memory_limit_bytes {
+                    set_resource_limit(libc::RLIMIT_AS, memory_limit_bytes)?;
+                }
+
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn limit_process(&self, _command: &mut Command, cpu_limit_secs: Option<u64>) {
+        if cpu_limit_secs.is_some() || self.script_memory_limit_mb.is_some() {
+            log::warn!("assertion script resource limits are only supported on unix");
+        }
+    }
+}
+
+/// Returns the interpreter specified by the shebang line of the script, if any.
+async fn read_shebang(file_path: &Path) -> Option<String> {
+    let mut file = tokio::fs::File::open(file_path).await.ok()?;
+    let mut buffer = [0u8; MAX_SHEBANG_BYTES];
+    let read_bytes = file.read(&mut buffer).await.ok()?;
+
+    let line = buffer[..read_bytes].strip_prefix(SHEBANG)?;
+    let line = match line.iter().position(|byte| *byte == b'\n') {
+        Some(line_end) => &line[..line_end],
+        None => line,
+    };
+
+    let interpreter = std::str::from_utf8(line).ok()?.trim();
+
+    if interpreter.is_empty() {
+        None
+    } else {
+        Some(interpreter.to_string())
+    }
+}
+
+#[cfg(unix)]
+async fn is_executable(file_path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match tokio::fs::metadata(file_path).await {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+async fn is_executable(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .is_some_and(|extension| extension == "exe")
+}
+
+/// Kills the process along with every process within its group, then reaps it.
+pub async fn kill_process(process: &mut Child) {
+    #[cfg(unix)]
+    if let Some(process_id) = process.id() {
+        // SAFETY: the process leads its own group, so only its processes are signaled.
+        unsafe {
+            libc::kill(-(process_id as libc::pid_t), libc::SIGKILL);
+        }
+    }
+
+    if let Err(error) = process.kill().await {
+        log::error!("failed to kill assertion process: {}", error);
+    }
+}
+
+/// Assertion worker of the suite, if started, along with the times it has been restarted.
+#[derive(Default)]
+struct WorkerState {
+    assert_worker: Option<Arc<AssertWorker>>,
+    restarts: u32,
+}
+
+/// Output written by an assertion script, truncated to a maximum size per stream.
+#[derive(Clone, Debug)]
+pub struct ScriptOutput {
+    stdout: String,
+    stderr: String,
+}
+
+impl ScriptOutput {
+    pub fn new(stdout: String, stderr: String) -> ScriptOutput {
+        ScriptOutput { stdout, stderr }
+    }
+
+    pub fn stdout(&self) -> &str {
+        self.stdout.as_str()
+    }
+
+    pub fn stderr(&self) -> &str {
+        self.stderr.as_str()
+    }
+}
+
+/// Output written into a stream by a script, keeping only up to the maximum output size.
+#[derive(Default)]
+pub struct OutputBuffer {
+    output: Vec<u8>,
+    discarded_bytes: usize,
+}
+
+impl OutputBuffer {
+    pub fn push(&mut self, bytes: &[u8]) {
+        let kept_bytes = bytes.len().min(MAX_SCRIPT_OUTPUT_BYTES - self.output.len());
+
+        self.output.extend_from_slice(&bytes[..kept_bytes]);
+        self.discarded_bytes += bytes.len() - kept_bytes;
+    }
+
+    pub fn into_string(self) -> String {
+        let mut output = String::from_utf8_lossy(self.output.as_slice()).into_owned();
+
+        if self.discarded_bytes > 0 {
+            output
+                .push_str(format!("\n... {} more bytes truncated", self.discarded_bytes).as_str());
+        }
+
+        output
+    }
+}
+
+fn create_command(runner: &ScriptRunner, file_path: &Path) -> Command {
+    match runner {
+        ScriptRunner::Interpreter(interpreter) if !interpreter.is_empty() => {
+            let mut command = Command::new(&interpreter[0]);
+            command.args(&interpreter[1..]).arg(file_path);
+            command
+        }
+        _ => Command::new(file_path),
+    }
+}
+
+fn create_envelope(metadata: Map<String, Value>, reply: &Reply) -> Result<Vec<u8>, Error> {
+    match serde_json::to_vec(&create_envelope_document(metadata, reply)) {
+        Ok(envelope) => Ok(envelope),
+        Err(error) => Err(Error::new(
+            ErrorKind::InternalFailure,
+            format!("failed to serialize reply envelope: {}", error),
+        )),
+    }
+}
+
+fn create_envelope_document(metadata: Map<String, Value>, reply: &Reply) -> Map<String, Value> {
+    let mut envelope = metadata;
+    envelope.insert("version".to_string(), Value::from(ENVELOPE_VERSION));
+    envelope.insert(
+        "body".to_string(),
+        serde_json::from_slice::<Value>(reply.data()).unwrap_or(Value::Null),
+    );
+    envelope.insert(
+        "body_base64".to_string(),
+        Value::from(base64::engine::general_purpose::STANDARD.encode(reply.data())),
+    );
+
+    envelope
+}
+
+async fn write_input(stdin: Option<ChildStdin>, input: Vec<u8>) -> Result<(), Error> {
+    let mut stdin = match stdin {
+        Some(stdin) => stdin,
+        None => return Ok(()),
+    };
+
+    match stdin.write_all(input.as_slice()).await {
+        Ok(()) => Ok(()),
+        // the script may end without reading the whole reply
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        Err(error) => Err(Error::new(
+            ErrorKind::InternalFailure,
+            format!("failed to write reply into the script's input: {}", error),
+        )),
+    }
+}
+
+/// Reads the whole stream, keeping only up to the maximum output size.
+async fn read_output<R: AsyncRead + Unpin>(stream: Option<R>) -> String {
+    let mut stream = match stream {
+        Some(stream) => stream,
+        None => return String::new(),
+    };
+
+    let mut output = OutputBuffer::default();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        match stream.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(read_bytes) => output.push(&buffer[..read_bytes]),
+            Err(error) => {
+                log::error!("failed to read assertion script output: {}", error);
+                break;
+            }
+        }
+    }
+
+    output.into_string()
+}
+
+/// Awaits the reader of an output stream, giving up on it if the stream is still open
+/// shortly after the script has ended.
+async fn collect_output(output_reader: JoinHandle<String>) -> String {
+    let abort_handle = output_reader.abort_handle();
+
+    match tokio::time::timeout(SCRIPT_OUTPUT_READ_TIMEOUT, output_reader).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(error)) => {
+            log::error!("failed to read assertion script output: {}", error);
+            String::new()
+        }
+        Err(_) => {
+            abort_handle.abort();
+            log::warn!(
+                "assertion script output still open {} ms after the script ended: discarding it",
+                SCRIPT_OUTPUT_READ_TIMEOUT.as_millis()
+            );
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ScriptFile {
+        file_path: PathBuf,
+    }
+
+    impl ScriptFile {
+        fn new(extension: &str, content: &str) -> ScriptFile {
+            let file_path =
+                std::env::temp_dir().join(format!("assert-{}.{}", uuid::Uuid::new_v4(), extension));
+            std::fs::write(&file_path, content).unwrap();
+
+            ScriptFile { file_path }
         }
     }
-}
\ No newline at end of file
+
+    impl Drop for ScriptFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.file_path);
+        }
+    }
+
+    fn assert_script_runner(suite_runner: Option<&str>) -> AssertScriptRunner {
+        AssertScriptRunner {
+            test_suite_name: Arc::new("suite".to_string()),
+            suite_runner: suite_runner.map(String::from),
+            assert_worker_path: None,
+            assert_worker: Mutex::new(WorkerState::default()),
+            python_bin: "python3".to_string(),
+            scripts_dir: std::env::temp_dir(),
+            script_timeout: Duration::from_secs(5),
+            script_cpu_limit_secs: None,
+            script_memory_limit_mb: None,
+            rhai_assert_engine: Arc::new(RhaiAssertEngine::try_new().unwrap()),
+        }
+    }
+
+    fn interpreter(command: &[&str]) -> ScriptRunner {
+        ScriptRunner::Interpreter(command.iter().map(|part| part.to_string()).collect())
+    }
+
+    #[tokio::test]
+    async fn reads_the_interpreter_of_the_shebang_line() {
+        let script = ScriptFile::new("py", "#!/usr/bin/env python3.11 -u\nprint()\n");
+        let no_shebang = ScriptFile::new("py", "print()\n");
+        let blank_shebang = ScriptFile::new("py", "#!  \nprint()\n");
+
+        assert_eq!(
+            read_shebang(script.file_path.as_path()).await.as_deref(),
+            Some("/usr/bin/env python3.11 -u")
+        );
+        assert_eq!(read_shebang(no_shebang.file_path.as_path()).await, None);
+        assert_eq!(read_shebang(blank_shebang.file_path.as_path()).await, None);
+    }
+
+    #[tokio::test]
+    async fn prefers_test_then_suite_runners_over_the_script() {
+        let script = ScriptFile::new("sh", "#!/bin/bash\nexit 0\n");
+        let file_path = script.file_path.as_path();
+
+        assert_eq!(
+            assert_script_runner(Some("node"))
+                .resolve_runner(Some("bash -e"), file_path)
+                .await,
+            interpreter(&["bash", "-e"])
+        );
+        assert_eq!(
+            assert_script_runner(Some("rhai"))
+                .resolve_runner(None, file_path)
+                .await,
+            ScriptRunner::Embedded
+        );
+        assert_eq!(
+            assert_script_runner(Some(" exec "))
+                .resolve_runner(None, file_path)
+                .await,
+            ScriptRunner::Executable
+        );
+    }
+
+    #[tokio::test]
+    async fn prefers_the_shebang_over_the_extension() {
+        let bash_script = ScriptFile::new("sh", "#!/bin/bash\nexit 0\n");
+        let shell_script = ScriptFile::new("sh", "exit 0\n");
+        let rhai_script = ScriptFile::new("rhai", "true");
+        let python_script = ScriptFile::new("check", "exit()\n");
+        let runner = assert_script_runner(None);
+
+        assert_eq!(
+            runner
+                .resolve_runner(None, bash_script.file_path.as_path())
+                .await,
+            interpreter(&["/bin/bash"])
+        );
+        assert_eq!(
+            runner
+                .resolve_runner(None, shell_script.file_path.as_path())
+                .await,
+            interpreter(&["sh"])
+        );
+        assert_eq!(
+            runner
+                .resolve_runner(None, rhai_script.file_path.as_path())
+                .await,
+            ScriptRunner::Embedded
+        );
+        assert_eq!(
+            runner
+                .resolve_runner(None, python_script.file_path.as_path())
+                .await,
+            interpreter(&["python3"])
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_python_scripts_with_the_configured_interpreter() {
+        let script = ScriptFile::new("py", "#!/usr/bin/env python3\nexit()\n");
+        let mut runner = assert_script_runner(None);
+        runner.python_bin = "/opt/venv/bin/python".to_string();
+
+        assert_eq!(
+            runner
+                .resolve_runner(None, script.file_path.as_path())
+                .await,
+            interpreter(&["/opt/venv/bin/python"])
+        );
+    }
+
+    fn test(script: &ScriptFile, script_timeout_ms: u64) -> Test {
+        serde_json::from_value(serde_json::json!({
+            "name": "test",
+            "request": {},
+            "assert_script": script.file_path.file_name().unwrap().to_str().unwrap(),
+            "script_timeout_ms": script_timeout_ms
+        }))
+        .unwrap()
+    }
+
+    fn reply() -> Reply {
+        Reply::new(
+            br#"{ "status": "ok" }"#.to_vec(),
+            "correlation".to_string(),
+            Duration::from_millis(1),
+            &lapin::BasicProperties::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn keeps_the_output_of_scripts_which_timed_out() {
+        let script = ScriptFile::new("sh", "echo started\necho failing >&2\nexec sleep 10\n");
+
+        let (result, output) = assert_script_runner(None)
+            .run_script(&test(&script, 200), &reply())
+            .await;
+        let output = output.unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::ScriptTimeout);
+        assert_eq!(output.stdout(), "started\n");
+        assert_eq!(output.stderr(), "failing\n");
+    }
+
+    #[tokio::test]
+    async fn captures_what_rhai_scripts_print() {
+        let script = ScriptFile::new("rhai", "print(reply.status); debug(\"checked\"); false");
+
+        let (result, output) = assert_script_runner(None)
+            .run_script(&test(&script, 5_000), &reply())
+            .await;
+        let output = output.unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TestAssertFailure);
+        assert_eq!(output.stdout(), "ok\n");
+        assert_eq!(output.stderr(), "\"checked\"\n");
+    }
+
+    #[test]
+    fn rejects_blank_runners() {
+        assert!(try_validate_runner("  ").is_err());
+        assert!(try_validate_runner("bash -e").is_ok());
+    }
+}
@@ -0,0 +1,392 @@
+//! Long-lived assertion worker, which asserts every reply of a suite without forking per reply.
+//!
+//! # Protocol
+//!
+//! The worker is started once per suite run, with `AMQP_TESTER_SUITE` set to the suite name,
+//! and is expected to exit once its standard input is closed, which the tester does once every
+//! test of the suite has ended. A worker which has not exited a few seconds later is killed
+//! along with its process group. A worker which exits while the suite is running is restarted
+//! a limited amount of times.
+//! Both directions exchange UTF-8 JSON documents, one per line.
+//!
+//! For every reply, the tester writes a request into the standard input of the worker:
+//! the same envelope given to scripts run with the `envelope` script input (`version`, `suite`,
+//! `test`, `correlation_id`, `latency_ms`, `content_type`, `headers`, `body` and `body_base64`)
+//! along with:
+//! - `id`: number identifying the request.
+//! - `script`: assert script of the test, or null, so a worker may dispatch on it.
+//!
+//! The worker answers each request by writing a response into its standard output:
+//! - `id`: id of the request being answered.
+//! - `passed`: whether the reply satisfies the worker's assertions.
+//! - `message`: optional failure message.
+//!
+//! Requests of parallel tests may be in flight at the same time, so responses may be written in
+//! any order. Responses to requests which already timed out are logged as late.
+//! Standard output is reserved for responses, anything written into the standard error
+//! is logged by the tester.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+
+use crate::error::{Error, ErrorKind};
+use crate::testing::assert_script_runner;
+
+/// Time given to the worker to exit once its standard input is closed, before it is killed.
+const WORKER_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct WorkerRequests {
+    /// Whether the worker has closed its standard output, after which no request is accepted.
+    exited: bool,
+    /// Requests awaiting their response.
+    pending: HashMap<u64, oneshot::Sender<WorkerResponse>>,
+    /// Requests which timed out, so their responses are told apart from unknown ones.
+    timed_out: HashSet<u64>,
+}
+
+type SharedRequests = Arc<Mutex<WorkerRequests>>;
+
+#[derive(Deserialize)]
+struct WorkerResponse {
+    id: u64,
+    passed: bool,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+pub struct AssertWorker {
+    worker_path: PathBuf,
+    /// Taken once the worker is stopped, which closes it.
+    stdin: tokio::sync::Mutex<Option<ChildStdin>>,
+    requests: SharedRequests,
+    next_request_id: AtomicU64,
+    process: tokio::sync::Mutex<Child>,
+}
+
+impl AssertWorker {
+    /// Starts the worker with the given command, whose standard streams are taken over.
+    pub fn try_start(
+        mut command: Command,
+        worker_path: &Path,
+        test_suite_name: &str,
+    ) -> Result<AssertWorker, Error> {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut process = match command.spawn() {
+            Ok(process) => process,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "failed to spawn assertion worker '{}': {}",
+                        worker_path.display(),
+                        error
+                    ),
+                ))
+            }
+        };
+
+        let (stdin, stdout, stderr) = match (
+            process.stdin.take(),
+            process.stdout.take(),
+            process.stderr.take(),
+        ) {
+            (Some(stdin), Some(stdout), Some(stderr)) => (stdin, stdout, stderr),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    "failed to take the standard streams of the assertion worker",
+                ))
+            }
+        };
+
+        let requests: SharedRequests = Arc::new(Mutex::new(WorkerRequests::default()));
+
+        tokio::spawn(read_responses(
+            stdout,
+            requests.clone(),
+            worker_path.to_path_buf(),
+        ));
+
+        let test_suite_name = test_suite_name.to_string();
+
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                log::info!("[{}] assertion worker: {}", test_suite_name, line);
+            }
+        });
+
+        Ok(AssertWorker {
+            worker_path: worker_path.to_path_buf(),
+            stdin: tokio::sync::Mutex::new(Some(stdin)),
+            requests,
+            next_request_id: AtomicU64::new(0),
+            process: tokio::sync::Mutex::new(process),
+        })
+    }
+
+    /// Whether the worker has closed its standard output, so it cannot respond anymore.
+    pub fn has_exited(&self) -> bool {
+        match self.requests.lock() {
+            Ok(requests) => requests.exited,
+            Err(_) => true,
+        }
+    }
+
+    /// Closes the standard input of the worker so it exits, killing it along with its process
+    /// group if it does not exit in time.
+    pub async fn stop(&self) {
+        self.stdin.lock().await.take();
+
+        let mut process = self.process.lock().await;
+
+        match tokio::time::timeout(WORKER_STOP_TIMEOUT, process.wait()).await {
+            Ok(Ok(_)) => (),
+            Ok(Err(error)) => {
+                log::error!(
+                    "failed to wait for assertion worker '{}' to exit: {}",
+                    self.worker_path.display(),
+                    error
+                );
+                assert_script_runner::kill_process(&mut process).await;
+            }
+            Err(_) => {
+                log::warn!(
+                    "assertion worker '{}' did not exit within {} ms: killing it",
+                    self.worker_path.display(),
+                    WORKER_STOP_TIMEOUT.as_millis()
+                );
+                assert_script_runner::kill_process(&mut process).await;
+            }
+        }
+    }
+
+    /// Sends the request to the worker and awaits its response.
+    pub async fn assert(
+        &self,
+        mut request: Map<String, Value>,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        request.insert("id".to_string(), Value::from(id));
+
+        let mut line = match serde_json::to_vec(&request) {
+            Ok(line) => line,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to serialize assertion worker request: {}", error),
+                ))
+            }
+        };
+        line.push(b'\n');
+
+        let (response_sender, response_receiver) = oneshot::channel();
+        self.try_add_pending_request(id, response_sender)?;
+
+        let write_result = match self.stdin.lock().await.as_mut() {
+            Some(stdin) => match stdin.write_all(line.as_slice()).await {
+                Ok(()) => stdin.flush().await,
+                Err(error) => Err(error),
+            },
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "worker has been stopped",
+            )),
+        };
+
+        if let Err(error) = write_result {
+            self.remove_pending_request(id);
+
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!(
+                    "failed to write request into assertion worker '{}': {}",
+                    self.worker_path.display(),
+                    error
+                ),
+            ));
+        }
+
+        let response = match tokio::time::timeout(timeout, response_receiver).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "assertion worker '{}' exited before responding",
+                        self.worker_path.display()
+                    ),
+                ))
+            }
+            Err(_) => {
+                if let Ok(mut requests) = self.requests.lock() {
+                    if requests.pending.remove(&id).is_some() {
+                        requests.timed_out.insert(id);
+                    }
+                }
+
+                return Err(Error::new(
+                    ErrorKind::ScriptTimeout,
+                    format!(
+                        "assertion worker '{}' did not respond within {} ms",
+                        self.worker_path.display(),
+                        timeout.as_millis()
+                    ),
+                ));
+            }
+        };
+
+        if response.passed {
+            return Ok(());
+        }
+
+        Err(Error::new(
+            ErrorKind::TestAssertFailure,
+            format!(
+                "assertion worker '{}' failed: {}",
+                self.worker_path.display(),
+                response
+                    .message
+                    .unwrap_or_else(|| "no message given".to_string())
+            ),
+        ))
+    }
+
+    fn try_add_pending_request(
+        &self,
+        id: u64,
+        response_sender: oneshot::Sender<WorkerResponse>,
+    ) -> Result<(), Error> {
+        let mut requests = match self.requests.lock() {
+            Ok(requests) => requests,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to lock assertion worker requests: {}", error),
+                ))
+            }
+        };
+
+        if requests.exited {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!(
+                    "assertion worker '{}' has exited",
+                    self.worker_path.display()
+                ),
+            ));
+        }
+
+        requests.pending.insert(id, response_sender);
+        Ok(())
+    }
+
+    fn remove_pending_request(&self, id: u64) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.pending.remove(&id);
+        }
+    }
+}
+
+impl Drop for AssertWorker {
+    /// Kills the process group of a worker which has not been stopped,
+    /// since only the worker itself would be killed on drop.
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            let process = self.process.get_mut();
+
+            if let (Ok(None), Some(process_id)) = (process.try_wait(), process.id()) {
+                // SAFETY: the process group was created for the worker, so only its processes
+                // are signaled.
+                unsafe {
+                    libc::kill(-(process_id as libc::pid_t), libc::SIGKILL);
+                }
+            }
+        }
+    }
+}
+
+/// Dispatches every response to its request until the worker closes its standard output,
+/// after which the requests still pending are dropped so they fail.
+async fn read_responses(stdout: ChildStdout, requests: SharedRequests, worker_path: PathBuf) {
+    let mut lines = BufReader::new(stdout).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(error) => {
+                log::error!(
+                    "failed to read response of assertion worker '{}': {}",
+                    worker_path.display(),
+                    error
+                );
+                break;
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<WorkerResponse>(line.as_str()) {
+            Ok(response) => response,
+            Err(error) => {
+                log::error!(
+                    "invalid response of assertion worker '{}': {}",
+                    worker_path.display(),
+                    error
+                );
+                continue;
+            }
+        };
+
+        let (response_sender, timed_out) = match requests.lock() {
+            Ok(mut requests) => (
+                requests.pending.remove(&response.id),
+                requests.timed_out.remove(&response.id),
+            ),
+            Err(_) => (None, false),
+        };
+
+        match response_sender {
+            Some(response_sender) => {
+                let _ = response_sender.send(response);
+            }
+            None if timed_out => log::warn!(
+                "assertion worker '{}' responded to request {} after its timeout",
+                worker_path.display(),
+                response.id
+            ),
+            None => log::error!(
+                "assertion worker '{}' responded to unknown request {}",
+                worker_path.display(),
+                response.id
+            ),
+        }
+    }
+
+    if let Ok(mut requests) = requests.lock() {
+        requests.exited = true;
+        requests.pending.clear();
+    }
+}
@@ -0,0 +1,414 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use jsonpath_rust::JsonPath;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{Error, ErrorKind};
+
+const JSON_PATH_ROOT: char = '$';
+
+/// Declarative check on the value(s) found at a path of a JSON reply.
+/// Paths starting with '$' are treated as JSONPath queries, any other path as a JSON pointer.
+/// Every check but `exists` must hold for each value found at the path.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Expectation {
+    path: String,
+    #[serde(flatten)]
+    check: Check,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Equals(Value),
+    NotEquals(Value),
+    /// Whether any value has to be found at the path or none at all.
+    Exists(bool),
+    Type(JsonType),
+    /// Regular expression which string values have to match.
+    Regex(String),
+    /// Inclusive bounds of numeric values.
+    Range {
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+    /// Amount of elements of arrays and objects, or characters of strings.
+    Length(usize),
+    /// Value which has to be contained within the value found, recursively for objects and arrays.
+    Subset(Value),
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonType {
+    Null,
+    Boolean,
+    Number,
+    Integer,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            JsonType::Null => value.is_null(),
+            JsonType::Boolean => value.is_boolean(),
+            JsonType::Number => value.is_number(),
+            JsonType::Integer => value.is_i64() || value.is_u64(),
+            JsonType::String => value.is_string(),
+            JsonType::Array => value.is_array(),
+            JsonType::Object => value.is_object(),
+        }
+    }
+}
+
+impl Expectation {
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    /// Checks that the path and the check are well formed, so mistakes are reported
+    /// before any request is sent.
+    pub fn try_validate(&self) -> Result<(), Error> {
+        if self.is_json_path() {
+            self.try_parse_json_path()?;
+        } else if !self.path.is_empty() && !self.path.starts_with('/') {
+            return Err(self.invalid(format!(
+                "JSON pointer '{}' must be empty or start with '/'",
+                self.path
+            )));
+        }
+
+        match &self.check {
+            Check::Regex(pattern) => {
+                self.try_compile_regex(pattern)?;
+            }
+            Check::Range { min, max } => match (min, max) {
+                (None, None) => {
+                    return Err(self.invalid("range requires at least 'min' or 'max'"));
+                }
+                (Some(min), Some(max)) if min > max => {
+                    return Err(self.invalid(format!(
+                        "range minimum {} is greater than its maximum {}",
+                        min, max
+                    )));
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates the expectation against the reply, returning the reason why it does not hold.
+    pub fn evaluate(&self, reply: &Value) -> Result<(), String> {
+        let values = self.find(reply)?;
+
+        if let Check::Exists(exists) = self.check {
+            return match (exists, values.is_empty()) {
+                (true, true) => Err("no value found".to_string()),
+                (false, false) => Err(format!("unexpected value {}", values[0])),
+                _ => Ok(()),
+            };
+        }
+
+        if values.is_empty() {
+            return Err("no value found".to_string());
+        }
+
+        let regex = match &self.check {
+            Check::Regex(pattern) => Some(
+                self.try_compile_regex(pattern)
+                    .map_err(|error| error.to_string())?,
+            ),
+            _ => None,
+        };
+
+        for value in values.iter() {
+            self.evaluate_value(value, regex.as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    fn evaluate_value(&self, value: &Value, regex: Option<&Regex>) -> Result<(), String> {
+        match &self.check {
+            Check::Equals(expected) => {
+                if value != expected {
+                    return Err(format!("expected {} but got {}", expected, value));
+                }
+            }
+            Check::NotEquals(unexpected) => {
+                if value == unexpected {
+                    return Err(format!("expected a value other than {}", unexpected));
+                }
+            }
+            Check::Exists(_) => (),
+            Check::Type(json_type) => {
+                if !json_type.matches(value) {
+                    return Err(format!("expected {:?} but got {}", json_type, value));
+                }
+            }
+            Check::Regex(pattern) => match value.as_str() {
+                Some(string) => {
+                    if !regex.is_some_and(|regex| regex.is_match(string)) {
+                        return Err(format!("{} does not match '{}'", value, pattern));
+                    }
+                }
+                None => return Err(format!("expected a string but got {}", value)),
+            },
+            Check::Range { min, max } => {
+                let number = match value.as_f64() {
+                    Some(number) => number,
+                    None => return Err(format!("expected a number but got {}", value)),
+                };
+
+                if min.is_some_and(|min| number < min) || max.is_some_and(|max| number > max) {
+                    return Err(format!(
+                        "{} is out of range [{}, {}]",
+                        number,
+                        display_bound(min),
+                        display_bound(max)
+                    ));
+                }
+            }
+            Check::Length(length) => {
+                let actual_length = match value {
+                    Value::Array(array) => array.len(),
+                    Value::Object(object) => object.len(),
+                    Value::String(string) => string.chars().count(),
+                    _ => {
+                        return Err(format!(
+                            "expected an array, object or string but got {}",
+                            value
+                        ))
+                    }
+                };
+
+                if actual_length != *length {
+                    return Err(format!(
+                        "expected length {} but got {}",
+                        length, actual_length
+                    ));
+                }
+            }
+            Check::Subset(subset) => {
+                if !is_subset(subset, value) {
+                    return Err(format!("{} is not contained within {}", subset, value));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, reply: &Value) -> Result<Vec<Value>, String> {
+        if self.is_json_path() {
+            let json_path = self
+                .try_parse_json_path()
+                .map_err(|error| error.to_string())?;
+
+            Ok(json_path
+                .find_slice_ptr(reply)
+                .into_iter()
+                .map(|value| (*value).clone())
+                .collect())
+        } else {
+            Ok(reply
+                .pointer(self.path.as_str())
+                .into_iter()
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn is_json_path(&self) -> bool {
+        self.path.starts_with(JSON_PATH_ROOT)
+    }
+
+    fn try_parse_json_path(&self) -> Result<JsonPath<Value>, Error> {
+        match JsonPath::from_str(self.path.as_str()) {
+            Ok(json_path) => Ok(json_path),
+            Err(error) => Err(self.invalid(format!("invalid JSONPath: {}", error))),
+        }
+    }
+
+    fn try_compile_regex(&self, pattern: &str) -> Result<Regex, Error> {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(regex),
+            Err(error) => Err(self.invalid(format!("invalid regex '{}': {}", pattern, error))),
+        }
+    }
+
+    fn invalid<T: Display>(&self, message: T) -> Error {
+        Error::new(
+            ErrorKind::InternalFailure,
+            format!("invalid expectation on '{}': {}", self.path, message),
+        )
+    }
+}
+
+/// Evaluates every expectation against the document, reporting all the ones that do not hold
+/// along with the subject naming the document.
+pub fn assert_expectations(
+    expectations: &[Expectation],
+    document: &Value,
+    subject: &str,
+) -> Result<(), Error> {
+    let failures = expectations
+        .iter()
+        .filter_map(|expectation| match expectation.evaluate(document) {
+            Ok(()) => None,
+            Err(reason) => Some(format!("'{}': {}", expectation.path(), reason)),
+        })
+        .collect::<Vec<String>>();
+
+    if !failures.is_empty() {
+        return Err(Error::new(
+            ErrorKind::TestAssertFailure,
+            format!("unmet expectations on {}: {}", subject, failures.join("; ")),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether every element of the subset is contained within the value:
+/// object entries must be found under the same key, array elements within any element.
+fn is_subset(subset: &Value, value: &Value) -> bool {
+    match (subset, value) {
+        (Value::Object(subset), Value::Object(object)) => subset.iter().all(|(key, subset)| {
+            object
+                .get(key)
+                .is_some_and(|value| is_subset(subset, value))
+        }),
+        (Value::Array(subset), Value::Array(array)) => subset
+            .iter()
+            .all(|subset| array.iter().any(|value| is_subset(subset, value))),
+        _ => subset == value,
+    }
+}
+
+fn display_bound(bound: &Option<f64>) -> String {
+    match bound {
+        Some(bound) => bound.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn expectation(expectation: Value) -> Expectation {
+        serde_json::from_value(expectation).unwrap()
+    }
+
+    fn reply() -> Value {
+        json!({
+            "status": "ok",
+            "items": [
+                { "id": 1, "price": 9.5, "tags": ["new", "sale"] },
+                { "id": 2, "price": 20.0, "tags": ["new"] }
+            ]
+        })
+    }
+
+    #[test]
+    fn evaluates_json_pointers() {
+        assert!(expectation(json!({ "path": "/status", "equals": "ok" }))
+            .evaluate(&reply())
+            .is_ok());
+        assert!(expectation(json!({ "path": "/items/1/id", "equals": 1 }))
+            .evaluate(&reply())
+            .is_err());
+        assert!(expectation(json!({ "path": "/missing", "exists": false }))
+            .evaluate(&reply())
+            .is_ok());
+        assert!(expectation(json!({ "path": "/missing", "equals": null }))
+            .evaluate(&reply())
+            .is_err());
+    }
+
+    #[test]
+    fn evaluates_every_value_found_by_json_paths() {
+        assert!(
+            expectation(json!({ "path": "$.items[*].id", "type": "integer" }))
+                .evaluate(&reply())
+                .is_ok()
+        );
+        assert!(
+            expectation(json!({ "path": "$.items[*].tags", "length": 2 }))
+                .evaluate(&reply())
+                .is_err()
+        );
+        assert!(
+            expectation(json!({ "path": "$.items[?(@.id == 3)]", "exists": true }))
+                .evaluate(&reply())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn evaluates_inclusive_ranges() {
+        let range = |min: Value, max: Value| {
+            expectation(json!({ "path": "$.items[*].price", "range": { "min": min, "max": max } }))
+                .evaluate(&reply())
+        };
+
+        assert!(range(json!(9.5), json!(20)).is_ok());
+        assert!(range(json!(10), Value::Null).is_err());
+        assert!(range(Value::Null, json!(19.99)).is_err());
+        assert!(
+            expectation(json!({ "path": "/status", "range": { "min": 0 } }))
+                .evaluate(&reply())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn evaluates_subsets_recursively() {
+        let subset =
+            |subset: Value| expectation(json!({ "path": "", "subset": subset })).evaluate(&reply());
+
+        assert!(subset(json!({ "items": [{ "tags": ["sale"] }] })).is_ok());
+        assert!(subset(json!({ "items": [{ "id": 2, "tags": ["sale"] }] })).is_err());
+        assert!(subset(json!({ "status": "ok", "total": 2 })).is_err());
+    }
+
+    #[test]
+    fn validates_paths_and_checks() {
+        let validate = |value: Value| expectation(value).try_validate();
+
+        assert!(validate(json!({ "path": "status", "exists": true })).is_err());
+        assert!(validate(json!({ "path": "$.[", "exists": true })).is_err());
+        assert!(validate(json!({ "path": "/status", "regex": "(" })).is_err());
+        assert!(validate(json!({ "path": "/id", "range": {} })).is_err());
+        assert!(validate(json!({ "path": "/id", "range": { "min": 2, "max": 1 } })).is_err());
+        assert!(validate(json!({ "path": "/status", "regex": "^o" })).is_ok());
+    }
+
+    #[test]
+    fn reports_every_unmet_expectation() {
+        let expectations = vec![
+            expectation(json!({ "path": "/status", "equals": "error" })),
+            expectation(json!({ "path": "/items", "length": 2 })),
+            expectation(json!({ "path": "/missing", "exists": true })),
+        ];
+
+        let error = assert_expectations(expectations.as_slice(), &reply(), "reply").unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::TestAssertFailure);
+        assert!(error.message().contains("'/status'"));
+        assert!(!error.message().contains("'/items'"));
+        assert!(error.message().contains("'/missing'"));
+    }
+}
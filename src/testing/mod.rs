@@ -1,11 +1,24 @@
 mod test;
 pub mod suite_reader;
+pub mod suite_discovery;
 mod test_result;
+pub mod test_filter;
 mod run_instance;
 mod run_mode;
-mod suite;
+pub mod suite;
+pub mod suite_planner;
 pub mod suite_result;
 pub mod suite_result_output;
 pub mod suite_runner;
 mod test_type;
 mod assert_script_runner;
+mod assert_worker;
+mod expectation;
+mod reply;
+mod reply_dispatcher;
+mod reply_mode;
+mod reply_schema;
+mod request_properties;
+mod rhai_assert_engine;
+mod stray_reply;
+mod suite_connection;
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::BasicProperties;
+use serde_json::{Map, Number, Value};
+
+const CONTENT_TYPE_PROPERTY: &str = "content_type";
+
+/// Reply received for a test request, along with the metadata which is given to assertion scripts.
+pub struct Reply {
+    data: Vec<u8>,
+    correlation_id: String,
+    latency: Duration,
+    headers: Map<String, Value>,
+    properties: Map<String, Value>,
+}
+
+impl Reply {
+    pub fn new(
+        data: Vec<u8>,
+        correlation_id: String,
+        latency: Duration,
+        properties: &BasicProperties,
+    ) -> Reply {
+        Reply {
+            data,
+            correlation_id,
+            latency,
+            headers: properties
+                .headers()
+                .as_ref()
+                .map(field_table_to_json)
+                .unwrap_or_default(),
+            properties: properties_to_json(properties),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    pub fn correlation_id(&self) -> &str {
+        self.correlation_id.as_str()
+    }
+
+    /// Time elapsed between the request being published and its reply being received.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.properties
+            .get(CONTENT_TYPE_PROPERTY)
+            .and_then(Value::as_str)
+    }
+
+    /// AMQP headers of the reply, converted to JSON.
+    pub fn headers(&self) -> &Map<String, Value> {
+        &self.headers
+    }
+
+    /// AMQP properties of the reply which tests may have expectations on, converted to JSON.
+    /// Properties which were not set are left out.
+    pub fn properties(&self) -> &Map<String, Value> {
+        &self.properties
+    }
+
+    /// Describes the reply and the test it belongs to, without its payload.
+    pub fn metadata(&self, suite_name: &str, test_name: &str) -> Map<String, Value> {
+        let mut metadata = Map::new();
+        metadata.insert("suite".to_string(), Value::from(suite_name));
+        metadata.insert("test".to_string(), Value::from(test_name));
+        metadata.insert(
+            "correlation_id".to_string(),
+            Value::from(self.correlation_id.as_str()),
+        );
+        metadata.insert(
+            "latency_ms".to_string(),
+            float_to_json(self.latency.as_secs_f64() * 1000.0),
+        );
+        metadata.insert("content_type".to_string(), Value::from(self.content_type()));
+        metadata.insert("headers".to_string(), Value::Object(self.headers.clone()));
+
+        metadata
+    }
+}
+
+fn properties_to_json(properties: &BasicProperties) -> Map<String, Value> {
+    let mut document = Map::new();
+
+    let string_properties = [
+        (CONTENT_TYPE_PROPERTY, properties.content_type()),
+        ("content_encoding", properties.content_encoding()),
+        ("type", properties.kind()),
+        ("app_id", properties.app_id()),
+        ("expiration", properties.expiration()),
+    ];
+
+    for (name, value) in string_properties {
+        if let Some(value) = value {
+            document.insert(name.to_string(), Value::from(value.as_str()));
+        }
+    }
+
+    if let Some(priority) = properties.priority() {
+        document.insert("priority".to_string(), Value::from(*priority));
+    }
+
+    if let Some(delivery_mode) = properties.delivery_mode() {
+        document.insert("delivery_mode".to_string(), Value::from(*delivery_mode));
+    }
+
+    if let Some(headers) = properties.headers() {
+        document.insert(
+            "headers".to_string(),
+            Value::Object(field_table_to_json(headers)),
+        );
+    }
+
+    document
+}
+
+pub fn field_table_to_json(field_table: &FieldTable) -> Map<String, Value> {
+    field_table
+        .inner()
+        .iter()
+        .map(|(key, value)| (key.to_string(), amqp_value_to_json(value)))
+        .collect()
+}
+
+fn amqp_value_to_json(value: &AMQPValue) -> Value {
+    match value {
+        AMQPValue::Boolean(value) => Value::Bool(*value),
+        AMQPValue::ShortShortInt(value) => Value::from(*value),
+        AMQPValue::ShortShortUInt(value) => Value::from(*value),
+        AMQPValue::ShortInt(value) => Value::from(*value),
+        AMQPValue::ShortUInt(value) => Value::from(*value),
+        AMQPValue::LongInt(value) => Value::from(*value),
+        AMQPValue::LongUInt(value) => Value::from(*value),
+        AMQPValue::LongLongInt(value) => Value::from(*value),
+        AMQPValue::Timestamp(value) => Value::from(*value),
+        AMQPValue::Float(value) => float_to_json(f64::from(*value)),
+        AMQPValue::Double(value) => float_to_json(*value),
+        AMQPValue::DecimalValue(value) => {
+            float_to_json(f64::from(value.value) / 10f64.powi(i32::from(value.scale)))
+        }
+        AMQPValue::ShortString(value) => Value::String(value.to_string()),
+        AMQPValue::LongString(value) => {
+            Value::String(String::from_utf8_lossy(value.as_bytes()).into_owned())
+        }
+        AMQPValue::FieldArray(values) => {
+            Value::Array(values.as_slice().iter().map(amqp_value_to_json).collect())
+        }
+        AMQPValue::FieldTable(field_table) => Value::Object(field_table_to_json(field_table)),
+        AMQPValue::ByteArray(bytes) => Value::Array(
+            bytes
+                .as_slice()
+                .iter()
+                .map(|byte| Value::from(*byte))
+                .collect(),
+        ),
+        AMQPValue::Void => Value::Null,
+    }
+}
+
+fn float_to_json(value: f64) -> Value {
+    match Number::from_f64(value) {
+        Some(number) => Value::Number(number),
+        None => Value::Null,
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::TryStreamExt;
+use lapin::message::Delivery;
+use lapin::options::{BasicAckOptions, BasicCancelOptions, BasicConsumeOptions};
+use lapin::types::FieldTable;
+use lapin::{Channel, Consumer};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::amqp_connection_manager::AmqpChannel;
+use crate::error::{Error, ErrorKind};
+use crate::testing::reply_mode::DIRECT_REPLY_TO_QUEUE;
+use crate::testing::stray_reply::{StrayReason, StrayReply};
+
+/// Time given to the consumer to hand over the replies it already received once it is cancelled.
+const CONSUMER_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Consumes the replies of a reply queue and hands each one to the test awaiting it,
+/// matched by correlation id, so every test of a suite shares a single queue and consumer.
+/// Replies which no test is awaiting are collected as stray replies.
+pub struct ReplyDispatcher {
+    channel: AmqpChannel,
+    reply_queue_name: String,
+    consumer_tag: String,
+    dispatch_state: Arc<Mutex<DispatchState>>,
+    dispatch_task: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Default)]
+struct DispatchState {
+    /// Whether replies are still being consumed, otherwise no reply can be awaited anymore.
+    consuming: bool,
+    /// Requests of the suite by their correlation id, kept for the retention once no longer
+    /// awaited so duplicate and late replies can be told apart from unknown ones.
+    requests: HashMap<String, Request>,
+    /// Correlation ids of the requests no longer awaited, in the order they stopped being.
+    finished_requests: VecDeque<(Instant, String)>,
+    /// Time requests are kept for once no longer awaited, after which their replies are unknown.
+    retention: Duration,
+    stray_replies: Vec<StrayReply>,
+}
+
+struct Request {
+    test_name: String,
+    /// Sender of the reply while the test is still awaiting it.
+    reply_sender: Option<oneshot::Sender<Delivery>>,
+    answered: bool,
+}
+
+impl ReplyDispatcher {
+    /// Starts consuming the reply queue through the given channel,
+    /// which requests have to be published through when using direct reply-to.
+    /// Requests are remembered for the stray reply grace once no longer awaited,
+    /// so their late and duplicate replies are reported as such.
+    pub async fn try_start(
+        channel: AmqpChannel,
+        reply_queue_name: String,
+        mut consume_options: BasicConsumeOptions,
+        consume_arguments: FieldTable,
+        stray_reply_grace: Duration,
+    ) -> Result<ReplyDispatcher, Error> {
+        // direct reply-to only accepts consumers which do not acknowledge deliveries
+        if reply_queue_name == DIRECT_REPLY_TO_QUEUE {
+            consume_options.no_ack = true;
+        }
+
+        let consumer_tag = format!("{}#{}", reply_queue_name, uuid::Uuid::new_v4());
+
+        let consumer = match channel
+            .channel()
+            .basic_consume(
+                reply_queue_name.as_str(),
+                consumer_tag.as_str(),
+                consume_options,
+                consume_arguments,
+            )
+            .await
+        {
+            Ok(consumer) => consumer,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to consume replies: {}", error),
+                ))
+            }
+        };
+
+        let dispatch_state = Arc::new(Mutex::new(DispatchState {
+            consuming: true,
+            retention: stray_reply_grace,
+            ..DispatchState::default()
+        }));
+
+        let dispatch_task = tokio::spawn(dispatch_replies(
+            consumer,
+            dispatch_state.clone(),
+            consume_options.no_ack,
+        ));
+
+        Ok(ReplyDispatcher {
+            channel,
+            reply_queue_name,
+            consumer_tag,
+            dispatch_state,
+            dispatch_task: Mutex::new(Some(dispatch_task)),
+        })
+    }
+
+    pub fn channel(&self) -> &Channel {
+        self.channel.channel()
+    }
+
+    pub fn reply_queue_name(&self) -> &str {
+        self.reply_queue_name.as_str()
+    }
+
+    /// Whether replies are still being consumed through a channel which is still open,
+    /// on a connection which has not failed.
+    pub fn is_healthy(&self) -> bool {
+        if !self.channel.is_healthy() {
+            return false;
+        }
+
+        match self.dispatch_state.lock() {
+            Ok(dispatch_state) => dispatch_state.consuming,
+            Err(_) => false,
+        }
+    }
+
+    /// Registers the test awaiting the reply with the given correlation id,
+    /// which has to be done before its request is published.
+    pub fn try_register(
+        &self,
+        correlation_id: &str,
+        test_name: &str,
+    ) -> Result<oneshot::Receiver<Delivery>, Error> {
+        let mut dispatch_state = match self.dispatch_state.lock() {
+            Ok(dispatch_state) => dispatch_state,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to lock pending replies: {}", error),
+                ))
+            }
+        };
+
+        if !dispatch_state.consuming {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                "reply consumer has stopped",
+            ));
+        }
+
+        dispatch_state.forget_finished_requests();
+
+        let (reply_sender, reply_receiver) = oneshot::channel();
+
+        dispatch_state.requests.insert(
+            correlation_id.to_string(),
+            Request {
+                test_name: test_name.to_string(),
+                reply_sender: Some(reply_sender),
+                answered: false,
+            },
+        );
+
+        Ok(reply_receiver)
+    }
+
+    /// Marks the test as no longer awaiting its reply.
+    pub fn unregister(&self, correlation_id: &str) {
+        if let Ok(mut dispatch_state) = self.dispatch_state.lock() {
+            let awaited = match dispatch_state.requests.get_mut(correlation_id) {
+                Some(request) => request.reply_sender.take().is_some(),
+                None => false,
+            };
+
+            if awaited {
+                dispatch_state.finish(correlation_id);
+            }
+        }
+    }
+
+    /// Stops consuming replies once the grace period has elapsed, failing every test
+    /// still awaiting one, and returns the stray replies received meanwhile.
+    /// Replies received during the grace period are dispatched as usual, so late and duplicate
+    /// replies to the last tests are collected too.
+    pub async fn stop(&self, grace: Duration) -> Vec<StrayReply> {
+        if !grace.is_zero() && self.is_healthy() {
+            tokio::time::sleep(grace).await;
+        }
+
+        if let Err(error) = self
+            .channel()
+            .basic_cancel(self.consumer_tag.as_str(), BasicCancelOptions::default())
+            .await
+        {
+            log::error!("failed to cancel reply consumer: {}", error);
+        }
+
+        let dispatch_task = match self.dispatch_task.lock() {
+            Ok(mut dispatch_task) => dispatch_task.take(),
+            Err(_) => None,
+        };
+
+        if let Some(dispatch_task) = dispatch_task {
+            if tokio::time::timeout(CONSUMER_STOP_TIMEOUT, dispatch_task)
+                .await
+                .is_err()
+            {
+                log::error!(
+                    "reply consumer did not stop within {} ms",
+                    CONSUMER_STOP_TIMEOUT.as_millis()
+                );
+            }
+        }
+
+        match self.dispatch_state.lock() {
+            Ok(mut dispatch_state) => {
+                dispatch_state.consuming = false;
+                dispatch_state.requests.clear();
+                dispatch_state.finished_requests.clear();
+                std::mem::take(&mut dispatch_state.stray_replies)
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Hands every reply to the test awaiting it until the consumer stops,
+/// after which the tests still awaiting their reply are dropped so they fail.
+async fn dispatch_replies(
+    mut consumer: Consumer,
+    dispatch_state: Arc<Mutex<DispatchState>>,
+    no_ack: bool,
+) {
+    loop {
+        let delivery = match consumer.try_next().await {
+            Ok(Some(delivery)) => delivery,
+            Ok(None) => break,
+            Err(error) => {
+                log::error!("failed to get reply: {}", error);
+                break;
+            }
+        };
+
+        if !no_ack {
+            if let Err(error) = delivery.ack(BasicAckOptions::default()).await {
+                log::error!("failed to ack reply: {}", error);
+            }
+        }
+
+        let mut dispatch_state = match dispatch_state.lock() {
+            Ok(dispatch_state) => dispatch_state,
+            Err(_) => break,
+        };
+
+        if let Some(stray_reply) = dispatch_state.dispatch(delivery) {
+            log::warn!("received {}", stray_reply);
+            dispatch_state.stray_replies.push(stray_reply);
+        }
+    }
+
+    if let Ok(mut dispatch_state) = dispatch_state.lock() {
+        dispatch_state.consuming = false;
+
+        for request in dispatch_state.requests.values_mut() {
+            request.reply_sender.take();
+        }
+    }
+}
+
+impl DispatchState {
+    /// Hands the reply to the test awaiting it, otherwise returns it as a stray reply.
+    fn dispatch(&mut self, delivery: Delivery) -> Option<StrayReply> {
+        self.forget_finished_requests();
+
+        let correlation_id = match delivery.properties.correlation_id() {
+            Some(correlation_id) => correlation_id.to_string(),
+            None => {
+                return Some(StrayReply::new(
+                    StrayReason::MissingCorrelationId,
+                    None,
+                    None,
+                ))
+            }
+        };
+
+        let request = match self.requests.get_mut(correlation_id.as_str()) {
+            Some(request) => request,
+            None => {
+                return Some(StrayReply::new(
+                    StrayReason::UnknownCorrelationId,
+                    Some(correlation_id),
+                    None,
+                ))
+            }
+        };
+
+        let reason = match request.reply_sender.take() {
+            Some(reply_sender) => match reply_sender.send(delivery) {
+                Ok(()) => {
+                    request.answered = true;
+                    self.finish(correlation_id.as_str());
+                    return None;
+                }
+                Err(_) => {
+                    let test_name = request.test_name.clone();
+                    self.finish(correlation_id.as_str());
+
+                    return Some(StrayReply::new(
+                        StrayReason::Late,
+                        Some(correlation_id),
+                        Some(test_name),
+                    ));
+                }
+            },
+            None if request.answered => StrayReason::Duplicate,
+            None => StrayReason::Late,
+        };
+
+        Some(StrayReply::new(
+            reason,
+            Some(correlation_id),
+            Some(request.test_name.clone()),
+        ))
+    }
+
+    /// Starts the retention of the request, which is no longer awaited.
+    fn finish(&mut self, correlation_id: &str) {
+        self.finished_requests
+            .push_back((Instant::now(), correlation_id.to_string()));
+    }
+
+    /// Forgets the requests whose retention has elapsed, so the requests of long suites
+    /// do not pile up until the suite ends.
+    fn forget_finished_requests(&mut self) {
+        while let Some((finished_at, correlation_id)) = self.finished_requests.front() {
+            if finished_at.elapsed() < self.retention {
+                break;
+            }
+
+            self.requests.remove(correlation_id);
+            self.finished_requests.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lapin::BasicProperties;
+
+    use super::*;
+
+    fn delivery(correlation_id: Option<&str>) -> Delivery {
+        let mut properties = BasicProperties::default();
+
+        if let Some(correlation_id) = correlation_id {
+            properties = properties.with_correlation_id(correlation_id.into());
+        }
+
+        Delivery {
+            delivery_tag: 1,
+            exchange: "".into(),
+            routing_key: "reply".into(),
+            redelivered: false,
+            properties,
+            data: b"{}".to_vec(),
+            acker: Default::default(),
+        }
+    }
+
+    fn dispatch_state() -> DispatchState {
+        DispatchState {
+            retention: Duration::from_secs(60),
+            ..DispatchState::default()
+        }
+    }
+
+    fn register(
+        dispatch_state: &mut DispatchState,
+        correlation_id: &str,
+    ) -> oneshot::Receiver<Delivery> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+
+        dispatch_state.requests.insert(
+            correlation_id.to_string(),
+            Request {
+                test_name: "test".to_string(),
+                reply_sender: Some(reply_sender),
+                answered: false,
+            },
+        );
+
+        reply_receiver
+    }
+
+    fn stray(dispatch_state: &mut DispatchState, correlation_id: Option<&str>) -> String {
+        dispatch_state
+            .dispatch(delivery(correlation_id))
+            .expect("reply should be stray")
+            .to_string()
+    }
+
+    #[test]
+    fn hands_replies_to_their_test() {
+        let mut dispatch_state = dispatch_state();
+        let mut reply_receiver = register(&mut dispatch_state, "a");
+
+        assert!(dispatch_state.dispatch(delivery(Some("a"))).is_none());
+        assert_eq!(
+            reply_receiver
+                .try_recv()
+                .unwrap()
+                .properties
+                .correlation_id(),
+            &Some("a".into())
+        );
+    }
+
+    #[test]
+    fn reports_replies_without_a_known_correlation_id() {
+        let mut dispatch_state = dispatch_state();
+        let _reply_receiver = register(&mut dispatch_state, "a");
+
+        assert_eq!(
+            stray(&mut dispatch_state, None),
+            "reply without correlation id"
+        );
+        assert_eq!(
+            stray(&mut dispatch_state, Some("b")),
+            "reply with unknown correlation id 'b'"
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_replies() {
+        let mut dispatch_state = dispatch_state();
+        let _reply_receiver = register(&mut dispatch_state, "a");
+
+        assert!(dispatch_state.dispatch(delivery(Some("a"))).is_none());
+        assert_eq!(
+            stray(&mut dispatch_state, Some("a")),
+            "duplicate reply 'a' for test 'test'"
+        );
+    }
+
+    #[test]
+    fn reports_replies_to_tests_no_longer_awaiting_them() {
+        let mut dispatch_state = dispatch_state();
+        drop(register(&mut dispatch_state, "dropped"));
+        let _reply_receiver = register(&mut dispatch_state, "unregistered");
+
+        if let Some(request) = dispatch_state.requests.get_mut("unregistered") {
+            request.reply_sender.take();
+        }
+        dispatch_state.finish("unregistered");
+
+        assert_eq!(
+            stray(&mut dispatch_state, Some("dropped")),
+            "late reply 'dropped' for test 'test'"
+        );
+        assert_eq!(
+            stray(&mut dispatch_state, Some("unregistered")),
+            "late reply 'unregistered' for test 'test'"
+        );
+    }
+
+    #[test]
+    fn forgets_requests_once_their_retention_has_elapsed() {
+        let mut dispatch_state = DispatchState::default();
+        let _reply_receiver = register(&mut dispatch_state, "a");
+
+        assert!(dispatch_state.dispatch(delivery(Some("a"))).is_none());
+        assert_eq!(
+            stray(&mut dispatch_state, Some("a")),
+            "reply with unknown correlation id 'a'"
+        );
+        assert!(dispatch_state.requests.is_empty());
+        assert!(dispatch_state.finished_requests.is_empty());
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// Pseudo-queue which RabbitMQ delivers replies from, straight to the channel which published
+/// the request.
+pub const DIRECT_REPLY_TO_QUEUE: &str = "amq.rabbitmq.reply-to";
+
+/// Ways of receiving the replies of test requests.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReplyMode {
+    /// Replies are received through reply queues, which are declared and deleted by the tester.
+    #[default]
+    Queue,
+    /// Replies are received through RabbitMQ's direct reply-to, without any queue being declared.
+    DirectReplyTo,
+}
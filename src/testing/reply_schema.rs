@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{Error, ErrorKind};
+
+/// JSON Schema which the reply of a test has to match.
+/// It is either written inline or read from a file, relative to the suite file which references it.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(from = "SchemaSource", into = "SchemaSource")]
+pub struct ReplySchema {
+    source: SchemaSource,
+    /// Compiled once the schema is resolved, so it is not compiled again for every reply.
+    validator: Option<Arc<jsonschema::Validator>>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+enum SchemaSource {
+    File(PathBuf),
+    Inline(Value),
+}
+
+impl From<SchemaSource> for ReplySchema {
+    fn from(source: SchemaSource) -> ReplySchema {
+        ReplySchema {
+            source,
+            validator: None,
+        }
+    }
+}
+
+impl From<ReplySchema> for SchemaSource {
+    fn from(reply_schema: ReplySchema) -> SchemaSource {
+        reply_schema.source
+    }
+}
+
+impl ReplySchema {
+    /// Reads the schema file, if any, so the schema is inlined, and compiles it,
+    /// which checks that it is a valid schema.
+    pub fn try_resolve(&mut self, suite_dir: &Path) -> Result<(), Error> {
+        if let SchemaSource::File(file) = &self.source {
+            let file = suite_dir.join(file.as_path());
+
+            let file_content = match std::fs::read(&file) {
+                Ok(file_content) => file_content,
+                Err(error) => {
+                    return Err(Error::new(
+                        ErrorKind::InternalFailure,
+                        format!("failed to read schema '{}': {}", file.display(), error),
+                    ));
+                }
+            };
+
+            match serde_json::from_slice::<Value>(file_content.as_slice()) {
+                Ok(schema) => self.source = SchemaSource::Inline(schema),
+                Err(error) => {
+                    return Err(Error::new(
+                        ErrorKind::InternalFailure,
+                        format!(
+                            "failed to deserialize schema '{}': {}",
+                            file.display(),
+                            error
+                        ),
+                    ));
+                }
+            }
+        }
+
+        self.validator = Some(Arc::new(self.try_create_validator()?));
+
+        Ok(())
+    }
+
+    /// Validates the reply against the schema, listing every violation along with its location.
+    pub fn validate(&self, reply: &Value) -> Result<(), Error> {
+        let validator = match &self.validator {
+            Some(validator) => validator,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    "schema has not been resolved",
+                ));
+            }
+        };
+
+        let violations = validator
+            .iter_errors(reply)
+            .map(|violation| {
+                let instance_path = violation.instance_path.to_string();
+
+                if instance_path.is_empty() {
+                    format!("'/': {}", violation)
+                } else {
+                    format!("'{}': {}", instance_path, violation)
+                }
+            })
+            .collect::<Vec<String>>();
+
+        if !violations.is_empty() {
+            return Err(Error::new(
+                ErrorKind::TestAssertFailure,
+                format!("reply does not match its schema: {}", violations.join("; ")),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn try_create_validator(&self) -> Result<jsonschema::Validator, Error> {
+        let schema = match &self.source {
+            SchemaSource::Inline(schema) => schema,
+            SchemaSource::File(file) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("schema '{}' has not been read", file.display()),
+                ));
+            }
+        };
+
+        match jsonschema::validator_for(schema) {
+            Ok(validator) => Ok(validator),
+            Err(error) => Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("invalid schema: {}", error),
+            )),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use lapin::types::{AMQPValue, FieldArray, FieldTable, ShortString};
+use lapin::BasicProperties;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::{Error, ErrorKind};
+
+const PERSISTENT_DELIVERY_MODE: u8 = 2;
+
+/// AMQP properties of a test request, which a suite sets for all of its tests
+/// and each test may override.
+/// Headers are written as JSON and converted into AMQP field values.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct RequestProperties {
+    #[serde(default)]
+    headers: Map<String, Value>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    app_id: Option<String>,
+    priority: Option<u8>,
+    /// Time to live of the request in milliseconds, as expected by the broker.
+    expiration: Option<String>,
+    message_id: Option<String>,
+    user_id: Option<String>,
+    /// 1 for transient requests, 2 for persistent ones.
+    delivery_mode: Option<u8>,
+}
+
+impl RequestProperties {
+    /// Checks that the properties can be published, so mistakes are reported
+    /// before any request is sent.
+    pub fn try_validate(&self) -> Result<(), Error> {
+        try_to_field_table(&self.headers)?;
+
+        if let Some(expiration) = &self.expiration {
+            if expiration.parse::<u64>().is_err() {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "expiration '{}' is not a number of milliseconds",
+                        expiration
+                    ),
+                ));
+            }
+        }
+
+        if let Some(delivery_mode) = self.delivery_mode {
+            if delivery_mode == 0 || delivery_mode > PERSISTENT_DELIVERY_MODE {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("delivery mode {} is neither 1 nor 2", delivery_mode),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns these properties overridden by the given ones, header by header.
+    pub fn overridden_by(&self, overrides: &RequestProperties) -> RequestProperties {
+        let mut headers = self.headers.clone();
+        headers.extend(overrides.headers.clone());
+
+        RequestProperties {
+            headers,
+            kind: overrides.kind.clone().or_else(|| self.kind.clone()),
+            app_id: overrides.app_id.clone().or_else(|| self.app_id.clone()),
+            priority: overrides.priority.or(self.priority),
+            expiration: overrides
+                .expiration
+                .clone()
+                .or_else(|| self.expiration.clone()),
+            message_id: overrides
+                .message_id
+                .clone()
+                .or_else(|| self.message_id.clone()),
+            user_id: overrides.user_id.clone().or_else(|| self.user_id.clone()),
+            delivery_mode: overrides.delivery_mode.or(self.delivery_mode),
+        }
+    }
+
+    /// Sets the properties on the request.
+    pub fn try_apply(&self, mut properties: BasicProperties) -> Result<BasicProperties, Error> {
+        if !self.headers.is_empty() {
+            properties = properties.with_headers(try_to_field_table(&self.headers)?);
+        }
+
+        if let Some(kind) = &self.kind {
+            properties = properties.with_type(kind.clone().into());
+        }
+
+        if let Some(app_id) = &self.app_id {
+            properties = properties.with_app_id(app_id.clone().into());
+        }
+
+        if let Some(priority) = self.priority {
+            properties = properties.with_priority(priority);
+        }
+
+        if let Some(expiration) = &self.expiration {
+            properties = properties.with_expiration(expiration.clone().into());
+        }
+
+        if let Some(message_id) = &self.message_id {
+            properties = properties.with_message_id(message_id.clone().into());
+        }
+
+        if let Some(user_id) = &self.user_id {
+            properties = properties.with_user_id(user_id.clone().into());
+        }
+
+        if let Some(delivery_mode) = self.delivery_mode {
+            properties = properties.with_delivery_mode(delivery_mode);
+        }
+
+        Ok(properties)
+    }
+}
+
+fn try_to_field_table(object: &Map<String, Value>) -> Result<FieldTable, Error> {
+    let mut field_table = FieldTable::default();
+
+    for (name, value) in object {
+        field_table.insert(
+            ShortString::from(name.clone()),
+            try_to_amqp_value(name, value)?,
+        );
+    }
+
+    Ok(field_table)
+}
+
+fn try_to_amqp_value(name: &str, value: &Value) -> Result<AMQPValue, Error> {
+    let value = match value {
+        Value::Null => AMQPValue::Void,
+        Value::Bool(value) => AMQPValue::Boolean(*value),
+        Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(value), _) => AMQPValue::LongLongInt(value),
+            (None, Some(value)) if !number.is_u64() => AMQPValue::Double(value),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("header '{}' has a number out of range: {}", name, number),
+                ))
+            }
+        },
+        Value::String(value) => AMQPValue::LongString(value.clone().into()),
+        Value::Array(values) => AMQPValue::FieldArray(FieldArray::from(
+            values
+                .iter()
+                .map(|value| try_to_amqp_value(name, value))
+                .collect::<Result<Vec<AMQPValue>, Error>>()?,
+        )),
+        Value::Object(object) => AMQPValue::FieldTable(try_to_field_table(object)?),
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use lapin::types::{LongString, ShortString};
+    use serde_json::json;
+
+    use super::*;
+
+    fn request_properties(request_properties: Value) -> RequestProperties {
+        serde_json::from_value(request_properties).unwrap()
+    }
+
+    #[test]
+    fn converts_json_headers_into_amqp_values() {
+        let field_table = try_to_field_table(
+            json!({
+                "null": null,
+                "flag": true,
+                "count": -3,
+                "ratio": 0.5,
+                "name": "tester",
+                "list": [1, "two"],
+                "nested": { "inner": false }
+            })
+            .as_object()
+            .unwrap(),
+        )
+        .unwrap();
+
+        let value = |name: &str| field_table.inner().get(&ShortString::from(name)).cloned();
+
+        assert_eq!(value("null"), Some(AMQPValue::Void));
+        assert_eq!(value("flag"), Some(AMQPValue::Boolean(true)));
+        assert_eq!(value("count"), Some(AMQPValue::LongLongInt(-3)));
+        assert_eq!(value("ratio"), Some(AMQPValue::Double(0.5)));
+        assert_eq!(
+            value("name"),
+            Some(AMQPValue::LongString(LongString::from("tester")))
+        );
+        assert_eq!(
+            value("list"),
+            Some(AMQPValue::FieldArray(FieldArray::from(vec![
+                AMQPValue::LongLongInt(1),
+                AMQPValue::LongString(LongString::from("two"))
+            ])))
+        );
+
+        let mut nested = FieldTable::default();
+        nested.insert(ShortString::from("inner"), AMQPValue::Boolean(false));
+        assert_eq!(value("nested"), Some(AMQPValue::FieldTable(nested)));
+    }
+
+    #[test]
+    fn rejects_numbers_out_of_range() {
+        assert!(
+            request_properties(json!({ "headers": { "big": u64::MAX } }))
+                .try_validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn validates_expiration_and_delivery_mode() {
+        assert!(
+            request_properties(json!({ "expiration": "60000", "delivery_mode": 2 }))
+                .try_validate()
+                .is_ok()
+        );
+        assert!(request_properties(json!({ "expiration": "1m" }))
+            .try_validate()
+            .is_err());
+        assert!(request_properties(json!({ "delivery_mode": 3 }))
+            .try_validate()
+            .is_err());
+    }
+
+    #[test]
+    fn overrides_properties_header_by_header() {
+        let suite_properties = request_properties(json!({
+            "headers": { "tenant": "a", "version": 1 },
+            "app_id": "suite",
+            "priority": 1
+        }));
+        let test_properties = request_properties(json!({
+            "headers": { "version": 2 },
+            "priority": 5
+        }));
+
+        let properties = suite_properties
+            .overridden_by(&test_properties)
+            .try_apply(BasicProperties::default())
+            .unwrap();
+
+        let headers = properties.headers().clone().unwrap();
+        assert_eq!(
+            headers.inner().get(&ShortString::from("tenant")),
+            Some(&AMQPValue::LongString(LongString::from("a")))
+        );
+        assert_eq!(
+            headers.inner().get(&ShortString::from("version")),
+            Some(&AMQPValue::LongLongInt(2))
+        );
+        assert_eq!(properties.app_id(), &Some(ShortString::from("suite")));
+        assert_eq!(properties.priority(), &Some(5));
+    }
+
+    #[test]
+    fn leaves_headers_unset_without_any() {
+        let properties = RequestProperties::default()
+            .try_apply(BasicProperties::default())
+            .unwrap();
+
+        assert!(properties.headers().is_none());
+    }
+}
@@ -0,0 +1,360 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde_json::{Map, Value};
+
+use crate::error::{Error, ErrorKind};
+use crate::testing::assert_script_runner::{OutputBuffer, ScriptOutput};
+
+pub const RHAI_SCRIPT_EXTENSION: &str = "rhai";
+
+const REPLY_VARIABLE: &str = "reply";
+const RAW_REPLY_VARIABLE: &str = "raw_reply";
+const METADATA_VARIABLE: &str = "metadata";
+
+/// Operations a script may run before being stopped, as a bound independent of the timeout.
+const MAX_SCRIPT_OPERATIONS: u64 = 100_000_000;
+/// Operations run between two checks of the deadline of a script, since reading the clock
+/// on every operation would slow scripts down.
+const DEADLINE_CHECK_OPERATIONS: u64 = 1_024;
+
+thread_local! {
+    /// Instant by which the script being run on this thread has to end.
+    static SCRIPT_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// Output printed by the script being run on this thread, through `print` and `debug`.
+    static SCRIPT_OUTPUT: RefCell<PrintedOutput> = RefCell::new(PrintedOutput::default());
+}
+
+#[derive(Default)]
+struct PrintedOutput {
+    stdout: OutputBuffer,
+    stderr: OutputBuffer,
+}
+
+/// Helpers available to every script, failing the test by throwing its failure message.
+const PRELUDE: &str = r#"
+fn fail(message) {
+    throw message;
+}
+
+fn assert(condition) {
+    if !condition {
+        throw "assertion failed";
+    }
+}
+
+fn assert(condition, message) {
+    if !condition {
+        throw message;
+    }
+}
+
+fn assert_eq(actual, expected) {
+    if actual != expected {
+        throw `expected ${expected} but got ${actual}`;
+    }
+}
+
+fn assert_eq(actual, expected, message) {
+    if actual != expected {
+        throw `${message}: expected ${expected} but got ${actual}`;
+    }
+}
+
+fn assert_ne(actual, unexpected) {
+    if actual == unexpected {
+        throw `expected a value other than ${unexpected}`;
+    }
+}
+
+fn assert_ne(actual, unexpected, message) {
+    if actual == unexpected {
+        throw `${message}: expected a value other than ${unexpected}`;
+    }
+}
+"#;
+
+/// Runs Rhai assertion scripts in-process.
+/// The reply is given to scripts as `reply`, decoded into a native map whenever it is JSON,
+/// and as `raw_reply`, the reply as a string. Its metadata is given as the `metadata` map.
+/// A script fails the test by throwing a message, either directly or through the prelude helpers
+/// (`fail`, `assert`, `assert_eq`, `assert_ne` and `matches`), or by evaluating to `false`.
+/// A script evaluating to any string also fails the test, with that string as its failure
+/// message, so a passing script must not end with a string expression.
+/// Scripts are stopped once their timeout elapses or they run too many operations.
+/// What scripts print through `print` and `debug` is captured as their standard output
+/// and standard error respectively.
+/// Scripts are compiled once and then reused for every reply.
+pub struct RhaiAssertEngine {
+    engine: Engine,
+    prelude: AST,
+    scripts: Mutex<HashMap<PathBuf, Arc<AST>>>,
+}
+
+impl RhaiAssertEngine {
+    pub fn try_new() -> Result<RhaiAssertEngine, Error> {
+        let mut engine = Engine::new();
+        engine.register_fn("matches", matches);
+        engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+        engine.on_print(|text| {
+            SCRIPT_OUTPUT.with_borrow_mut(|output| print_line(&mut output.stdout, text))
+        });
+        engine.on_debug(|text, _, _| {
+            SCRIPT_OUTPUT.with_borrow_mut(|output| print_line(&mut output.stderr, text))
+        });
+        engine.on_progress(|operations| {
+            if operations % DEADLINE_CHECK_OPERATIONS != 0 {
+                return None;
+            }
+
+            match SCRIPT_DEADLINE.get() {
+                Some(deadline) if Instant::now() >= deadline => Some(Dynamic::UNIT),
+                _ => None,
+            }
+        });
+
+        let prelude = match engine.compile(PRELUDE) {
+            Ok(prelude) => prelude,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to compile assertion script prelude: {}", error),
+                ));
+            }
+        };
+
+        Ok(RhaiAssertEngine {
+            engine,
+            prelude,
+            scripts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Runs the script against the reply, blocking the current thread until it ends,
+    /// so it must not be called from an async task.
+    /// What the script printed is returned along with its result whenever the script was run.
+    pub fn run_script(
+        &self,
+        file_path: &Path,
+        reply: &[u8],
+        metadata: Map<String, Value>,
+        timeout: Duration,
+    ) -> (Result<(), Error>, Option<ScriptOutput>) {
+        let script = match self.try_get_script(file_path) {
+            Ok(script) => script,
+            Err(error) => return (Err(error), None),
+        };
+
+        let mut scope = match create_scope(reply, metadata) {
+            Ok(scope) => scope,
+            Err(error) => return (Err(error), None),
+        };
+
+        // a timeout too long to be represented is treated as no timeout
+        SCRIPT_DEADLINE.set(Instant::now().checked_add(timeout));
+        SCRIPT_OUTPUT.take();
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, script.as_ref());
+        SCRIPT_DEADLINE.set(None);
+        let printed_output = SCRIPT_OUTPUT.take();
+
+        let output = ScriptOutput::new(
+            printed_output.stdout.into_string(),
+            printed_output.stderr.into_string(),
+        );
+
+        (assert_result(file_path, result, timeout), Some(output))
+    }
+
+    fn try_get_script(&self, file_path: &Path) -> Result<Arc<AST>, Error> {
+        let mut scripts = match self.scripts.lock() {
+            Ok(scripts) => scripts,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to lock compiled assertion scripts: {}", error),
+                ));
+            }
+        };
+
+        if let Some(script) = scripts.get(file_path) {
+            return Ok(script.clone());
+        }
+
+        let script = match self.engine.compile_file(file_path.to_path_buf()) {
+            Ok(script) => Arc::new(self.prelude.merge(&script)),
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "failed to compile assertion script '{}': {}",
+                        file_path.display(),
+                        error
+                    ),
+                ));
+            }
+        };
+
+        scripts.insert(file_path.to_path_buf(), script.clone());
+
+        Ok(script)
+    }
+}
+
+/// Creates the scope of a script run, holding the reply and its metadata.
+fn create_scope(reply: &[u8], metadata: Map<String, Value>) -> Result<Scope<'static>, Error> {
+    let reply = match std::str::from_utf8(reply) {
+        Ok(reply) => reply,
+        Err(error) => {
+            return Err(Error::new(
+                ErrorKind::TestAssertFailure,
+                format!("failed to decode reply for assertion script: {}", error),
+            ))
+        }
+    };
+
+    let decoded_reply = match serde_json::from_str::<Value>(reply) {
+        Ok(decoded_reply) => try_to_dynamic(decoded_reply)?,
+        Err(_) => Dynamic::from(reply.to_string()),
+    };
+
+    let mut scope = Scope::new();
+    scope.push_constant(REPLY_VARIABLE, decoded_reply);
+    scope.push_constant(RAW_REPLY_VARIABLE, reply.to_string());
+    scope.push_constant(METADATA_VARIABLE, try_to_dynamic(Value::Object(metadata))?);
+
+    Ok(scope)
+}
+
+/// Turns the result of a script into the result of the assertion.
+fn assert_result(
+    file_path: &Path,
+    result: Result<Dynamic, Box<EvalAltResult>>,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let failure_message = match result {
+        Ok(result) => {
+            // any string is a failure message, see the documentation of the engine
+            if let Ok(message) = result.clone().into_string() {
+                Some(message)
+            } else if result.as_bool() == Ok(false) {
+                Some("script evaluated to false".to_string())
+            } else {
+                None
+            }
+        }
+        Err(error) => match root_error(*error) {
+            EvalAltResult::ErrorTerminated(_, _) => {
+                return Err(Error::new(
+                    ErrorKind::ScriptTimeout,
+                    format!(
+                        "assertion script '{}' did not end within {} ms",
+                        file_path.display(),
+                        timeout.as_millis()
+                    ),
+                ));
+            }
+            EvalAltResult::ErrorTooManyOperations(_) => Some(format!(
+                "script exceeded {} operations",
+                MAX_SCRIPT_OPERATIONS
+            )),
+            EvalAltResult::ErrorRuntime(message, _) => Some(message.to_string()),
+            error => Some(format!("script errored: {}", error)),
+        },
+    };
+
+    match failure_message {
+        Some(failure_message) => Err(Error::new(
+            ErrorKind::TestAssertFailure,
+            format!(
+                "assertion script '{}' failed: {}",
+                file_path.display(),
+                failure_message
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn print_line(output: &mut OutputBuffer, text: &str) {
+    output.push(text.as_bytes());
+    output.push(b"\n");
+}
+
+fn try_to_dynamic(value: Value) -> Result<Dynamic, Error> {
+    match rhai::serde::to_dynamic(value) {
+        Ok(value) => Ok(value),
+        Err(error) => Err(Error::new(
+            ErrorKind::InternalFailure,
+            format!("failed to convert value for assertion script: {}", error),
+        )),
+    }
+}
+
+/// Extracts the error raised by a script, even if it was raised within a function such as a helper.
+fn root_error(error: EvalAltResult) -> EvalAltResult {
+    match error {
+        EvalAltResult::ErrorInFunctionCall(_, _, error, _) => root_error(*error),
+        error => error,
+    }
+}
+
+fn matches(text: &str, pattern: &str) -> Result<bool, Box<EvalAltResult>> {
+    match Regex::new(pattern) {
+        Ok(regex) => Ok(regex.is_match(text)),
+        Err(error) => Err(format!("invalid regex '{}': {}", pattern, error).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script: &str, timeout: Duration) -> Result<(), Error> {
+        let file_path = std::env::temp_dir().join(format!("assert-{}.rhai", uuid::Uuid::new_v4()));
+        std::fs::write(&file_path, script).unwrap();
+
+        let (result, _) = RhaiAssertEngine::try_new().unwrap().run_script(
+            file_path.as_path(),
+            br#"{ "status": "ok" }"#,
+            Map::new(),
+            timeout,
+        );
+
+        std::fs::remove_file(&file_path).unwrap();
+        result
+    }
+
+    #[test]
+    fn fails_through_helpers_false_and_strings() {
+        let timeout = Duration::from_secs(5);
+
+        assert!(run(r#"assert_eq(reply.status, "ok")"#, timeout).is_ok());
+
+        let error = run(r#"assert_eq(reply.status, "error")"#, timeout).unwrap_err();
+        assert!(error.message().contains("expected error but got ok"));
+
+        assert!(run("reply.status == \"error\"", timeout).is_err());
+
+        let error = run(r#"`status is ${reply.status}`"#, timeout).unwrap_err();
+        assert!(error.message().contains("status is ok"));
+    }
+
+    #[test]
+    fn stops_scripts_once_their_timeout_elapses() {
+        let error = run("fn spin() { loop {} } spin()", Duration::from_millis(50)).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::ScriptTimeout);
+    }
+
+    #[test]
+    fn runs_scripts_whose_timeout_cannot_be_represented() {
+        assert!(run(r#"assert_eq(reply.status, "ok")"#, Duration::MAX).is_ok());
+    }
+}
@@ -1,62 +1,129 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use futures_util::TryStreamExt;
-use lapin::{options::BasicAckOptions, BasicProperties, Channel};
+use lapin::message::Delivery;
+use lapin::publisher_confirm::Confirmation;
+use lapin::BasicProperties;
 use serde_json::Value;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
 
+use crate::testing::assert_script_runner::AssertScriptRunner;
+use crate::testing::expectation;
+use crate::testing::reply::Reply;
+use crate::testing::request_properties::RequestProperties;
+use crate::testing::suite_connection::{SuiteChannel, SuiteConnection};
+use crate::testing::test::Test;
+use crate::testing::test_result::TestResult;
 use crate::{
     config::amqp_instance_config::AmqpInstanceConfig,
     error::{Error, ErrorKind},
 };
-use crate::testing::assert_script_runner;
-use crate::testing::assert_script_runner::AssertScriptRunner;
-use crate::testing::test::Test;
-use crate::testing::test_result::TestResult;
 
 /// A single test instance that is run by the SuiteRunner.
 pub struct RunInstance {
     test: Arc<Test>,
-    channel: Channel,
-    request_queue_name: String,
-    reply_queue_name: String,
+    /// Resolved into a channel once the test runs, so the request is published through
+    /// a channel which is still open.
+    suite_connection: Arc<SuiteConnection>,
     amqp_instance: AmqpInstanceConfig,
     result_sender: Sender<TestResult>,
-    assert_script_runner: Arc<AssertScriptRunner>
+    assert_script_runner: Arc<AssertScriptRunner>,
+    cancellation_token: CancellationToken,
+    reply_timeout: Duration,
+    request_properties: RequestProperties,
 }
 
 impl RunInstance {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         test: Arc<Test>,
-        channel: Channel,
-        request_queue_name: String,
-        reply_queue_name: String,
+        suite_connection: Arc<SuiteConnection>,
         amqp_instance: AmqpInstanceConfig,
         result_sender: Sender<TestResult>,
-        assert_script_runner: Arc<AssertScriptRunner>
+        assert_script_runner: Arc<AssertScriptRunner>,
+        cancellation_token: CancellationToken,
+        reply_timeout: Duration,
+        request_properties: RequestProperties,
     ) -> Self {
         RunInstance {
             test,
-            channel,
-            request_queue_name,
-            reply_queue_name,
+            suite_connection,
             amqp_instance,
             result_sender,
-            assert_script_runner
+            assert_script_runner,
+            cancellation_token,
+            reply_timeout,
+            request_properties,
         }
     }
 
-    pub async fn run(mut self) -> Result<(), Error> {
-        let correlation_id = uuid::Uuid::new_v4().to_string();
+    /// Sends the test request and asserts its reply, which is awaited from the moment before
+    /// the request is published until it is received or the test is over.
+    /// Nothing is sent if the run has been cancelled, leaving the test to be reported as aborted.
+    /// Any failure preventing the test from finishing is reported as the result of the test,
+    /// so an error is only returned if the result itself cannot be sent.
+    pub async fn run(self) -> Result<(), Error> {
+        if self.cancellation_token.is_cancelled() {
+            return Ok(());
+        }
 
-        self = self.send_request(correlation_id.as_str()).await?;
-        self.get_reply(correlation_id.as_str()).await?;
+        let execution = match self.suite_connection.try_connect().await {
+            Ok(suite_channel) => self.execute(&suite_channel).await,
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = execution {
+            log::error!("[{}] test errored: {}", self.test.name(), error);
+            self.send_result(Err(error)).await?;
+        }
 
         Ok(())
     }
 
-    async fn send_request(self, correlation_id: &str) -> Result<Self, Error> {
-        log::info!("[{}] sending request with correlation_id: {}", self.test.name(), correlation_id);
+    /// Sends the request through the given channel and awaits its reply.
+    /// Both the confirmation of the request and its reply are awaited until the same deadline,
+    /// so a test never waits longer than its reply timeout.
+    async fn execute(&self, suite_channel: &SuiteChannel) -> Result<(), Error> {
+        let correlation_id = uuid::Uuid::new_v4().to_string();
+        let reply_dispatcher = suite_channel.reply_dispatcher();
+
+        let reply_receiver =
+            reply_dispatcher.try_register(correlation_id.as_str(), self.test.name())?;
+
+        let deadline = tokio::time::Instant::now() + self.reply_timeout;
+
+        let execution = match self
+            .send_request(suite_channel, correlation_id.as_str(), deadline)
+            .await
+        {
+            Ok(sent_at) => {
+                self.get_reply(reply_receiver, correlation_id.as_str(), sent_at, deadline)
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+
+        reply_dispatcher.unregister(correlation_id.as_str());
+        execution
+    }
+
+    /// Publishes the test request, returning the instant it was published at once the broker
+    /// confirms it. Unless the suite disables `mandatory`, the broker returns the request if it
+    /// cannot be routed to any queue, since no reply could ever be received for it.
+    /// The confirmation is awaited no longer than the deadline, nor once the run is cancelled.
+    async fn send_request(
+        &self,
+        suite_channel: &SuiteChannel,
+        correlation_id: &str,
+        deadline: tokio::time::Instant,
+    ) -> Result<Instant, Error> {
+        log::info!(
+            "[{}] sending request with correlation_id: {}",
+            self.test.name(),
+            correlation_id
+        );
 
         let request_payload = match serde_json::to_vec(self.test.request()) {
             Ok(request_payload) => request_payload,
@@ -68,132 +135,201 @@ impl RunInstance {
             }
         };
 
-        let request_properties = BasicProperties::default()
-            .with_content_type("application/json".into())
-            .with_correlation_id(correlation_id.into())
-            .with_reply_to(self.reply_queue_name.clone().into());
+        let request_properties = self.request_properties.try_apply(
+            BasicProperties::default()
+                .with_content_type("application/json".into())
+                .with_correlation_id(correlation_id.into())
+                .with_reply_to(suite_channel.reply_dispatcher().reply_queue_name().into()),
+        )?;
+
+        let sent_at = Instant::now();
 
-        match self
-            .channel
+        let publisher_confirm = match suite_channel
+            .channel()
             .basic_publish(
-                "",
-                self.request_queue_name.as_str(),
+                self.amqp_instance.exchange(),
+                self.amqp_instance
+                    .routing_key()
+                    .unwrap_or(suite_channel.request_queue_name()),
                 *self.amqp_instance.publish_options(),
                 request_payload.as_slice(),
                 request_properties,
             )
             .await
         {
-            Ok(_) => (),
+            Ok(publisher_confirm) => publisher_confirm,
             Err(error) => {
                 return Err(Error::new(
                     ErrorKind::InternalFailure,
                     format!("failed to publish request: {}", error),
                 ))
             }
-        }
-
-        Ok(self)
-    }
-
-    async fn get_reply(self, correlation_id: &str) -> Result<Self, Error> {
-        log::info!("[{}] getting reply for correlation id: {}", self.test.name(), correlation_id);
-
-        let consumer_tag = format!("{}#{}", &self.reply_queue_name, uuid::Uuid::new_v4());
+        };
 
-        let mut consumer = match self
-            .channel
-            .basic_consume(
-                self.reply_queue_name.as_str(),
-                consumer_tag.as_str(),
-                *self.amqp_instance.consume_options(),
-                self.amqp_instance.consume_arguments().clone(),
-            )
-            .await
-        {
-            Ok(consumer) => consumer,
-            Err(error) => {
+        let confirmation = tokio::select! {
+            confirmation = publisher_confirm => match confirmation {
+                Ok(confirmation) => confirmation,
+                Err(error) => {
+                    return Err(Error::new(
+                        ErrorKind::InternalFailure,
+                        format!("failed to get request confirmation: {}", error),
+                    ))
+                }
+            },
+            _ = tokio::time::sleep_until(deadline) => {
                 return Err(Error::new(
                     ErrorKind::InternalFailure,
-                    format!("failed to consume reply: {}", error),
+                    format!(
+                        "request not confirmed within {} ms",
+                        self.reply_timeout.as_millis()
+                    ),
+                ))
+            }
+            _ = self.cancellation_token.cancelled() => {
+                return Err(Error::new(
+                    ErrorKind::Aborted,
+                    "test aborted while waiting for its request confirmation",
                 ))
             }
         };
 
-        loop {
-            log::info!("[{}] trying to get next delivery", self.test.name());
+        match confirmation {
+            Confirmation::Ack(None) | Confirmation::NotRequested => Ok(sent_at),
+            Confirmation::Ack(Some(returned_request)) => Err(Error::new(
+                ErrorKind::RequestRejected,
+                format!(
+                    "request unroutable: {} {}",
+                    returned_request.reply_code, returned_request.reply_text
+                ),
+            )),
+            Confirmation::Nack(_) => Err(Error::new(
+                ErrorKind::RequestRejected,
+                "request nacked by the broker",
+            )),
+        }
+    }
 
-            let delivery = match consumer.try_next().await {
-                Ok(Some(delivery)) => delivery,
-                Ok(None) => {
-                    return Err(Error::new(
-                        ErrorKind::InternalFailure,
-                        "failed to get reply: no reply received",
-                    ))
-                }
-                Err(error) => {
+    async fn get_reply(
+        &self,
+        reply_receiver: oneshot::Receiver<Delivery>,
+        correlation_id: &str,
+        sent_at: Instant,
+        deadline: tokio::time::Instant,
+    ) -> Result<(), Error> {
+        log::info!(
+            "[{}] getting reply for correlation id: {}",
+            self.test.name(),
+            correlation_id
+        );
+
+        let delivery = tokio::select! {
+            delivery = reply_receiver => match delivery {
+                Ok(delivery) => delivery,
+                Err(_) => {
                     return Err(Error::new(
                         ErrorKind::InternalFailure,
-                        format!("failed to get reply: {}", error),
+                        "failed to get reply: reply consumer stopped",
                     ))
                 }
-            };
+            },
+            _ = tokio::time::sleep_until(deadline) => {
+                log::info!("[{}] timed out while waiting for its reply", self.test.name());
 
-            log::info!("[{}] received delivery", self.test.name());
+                return self
+                    .send_result(Err(Error::new(
+                        ErrorKind::ReplyTimeout,
+                        format!(
+                            "no reply received within {} ms",
+                            self.reply_timeout.as_millis()
+                        ),
+                    )))
+                    .await;
+            }
+            _ = self.cancellation_token.cancelled() => {
+                log::info!("[{}] aborted while waiting for its reply", self.test.name());
 
-            match delivery.ack(BasicAckOptions::default()).await {
-                Ok(_) => (),
-                Err(error) => {
-                    return Err(Error::new(
-                        ErrorKind::InternalFailure,
-                        format!("failed to ack reply: {}", error),
-                    ))
-                }
+                return self
+                    .send_result(Err(Error::new(
+                        ErrorKind::Aborted,
+                        "test aborted while waiting for its reply",
+                    )))
+                    .await;
             }
+        };
 
-            if let Some(delivery_correlation_id) = delivery.properties.correlation_id() {
-                if delivery_correlation_id.as_str() == correlation_id {
-                    let assert_script = self.test.assert_script();
-
-                    match self.assert_script_runner.run_script(assert_script, delivery.data).await {
-                        Ok(_) => {
-                            if let Err(error) = self
-                                .result_sender
-                                .send(TestResult::new(self.test.name().to_string(), Ok(())))
-                                .await
-                            {
-                                return Err(Error::new(
-                                    ErrorKind::InternalFailure,
-                                    format!("failed to send result: {}", error),
-                                ));
-                            }
-                        },
-                        Err(assert_error) => {
-                            if let Err(error) = self
-                                .result_sender
-                                .send(TestResult::new(
-                                    self.test.name().to_string(),
-                                    Err(Error::new(
-                                        ErrorKind::TestAssertFailure,
-                                        assert_error.message(),
-                                        ),
-                                    )),
-                                )
-                                .await
-                            {
-                                return Err(Error::new(
-                                    ErrorKind::InternalFailure,
-                                    format!("failed to send result: {}", error),
-                                ));
-                            }
-                        }
-                    }
-
-                    break;
-                }
+        log::info!("[{}] received reply", self.test.name());
+
+        let reply = Reply::new(
+            delivery.data,
+            correlation_id.to_string(),
+            sent_at.elapsed(),
+            &delivery.properties,
+        );
+
+        let test_result = self.assert_reply(&reply).await;
+        self.send_test_result(test_result).await
+    }
+
+    /// Evaluates the expectations on the reply properties, validates the reply against the schema
+    /// of the test, evaluates its expectations and then runs its assert script or the assertion
+    /// worker of its suite, if any.
+    async fn assert_reply(&self, reply: &Reply) -> TestResult {
+        let test_name = self.test.name().to_string();
+
+        if let Err(error) = expectation::assert_expectations(
+            self.test.property_expectations(),
+            &Value::Object(reply.properties().clone()),
+            "reply properties",
+        ) {
+            return TestResult::new(test_name, Err(error));
+        }
+
+        if let Err(error) = self.assert_decoded_reply(reply) {
+            return TestResult::new(test_name, Err(error));
+        }
+
+        let (result, script_output) = self
+            .assert_script_runner
+            .run_script(&self.test, reply)
+            .await;
+
+        TestResult::new(test_name, result).with_script_output(script_output)
+    }
+
+    fn assert_decoded_reply(&self, reply: &Reply) -> Result<(), Error> {
+        if self.test.expectations().is_empty() && self.test.schema().is_none() {
+            return Ok(());
+        }
+
+        let decoded_reply = match serde_json::from_slice::<Value>(reply.data()) {
+            Ok(decoded_reply) => decoded_reply,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::TestAssertFailure,
+                    format!("reply is not valid JSON: {}", error),
+                ));
             }
+        };
+
+        if let Some(schema) = self.test.schema() {
+            schema.validate(&decoded_reply)?;
         }
 
-        Ok(self)
+        expectation::assert_expectations(self.test.expectations(), &decoded_reply, "reply")
+    }
+
+    async fn send_result(&self, result: Result<(), Error>) -> Result<(), Error> {
+        self.send_test_result(TestResult::new(self.test.name().to_string(), result))
+            .await
+    }
+
+    async fn send_test_result(&self, test_result: TestResult) -> Result<(), Error> {
+        match self.result_sender.send(test_result).await {
+            Ok(()) => Ok(()),
+            Err(error) => Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("failed to send result: {}", error),
+            )),
+        }
     }
 }
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 /// Modes for running test suites.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum RunMode {
     /// Each test is run sequentially in a single task.
     Sequential,
@@ -0,0 +1,65 @@
+use std::fmt;
+
+/// Reply which no test was awaiting, usually a symptom of the API echoing the wrong
+/// correlation id or replying more than once.
+#[derive(Clone, Debug)]
+pub struct StrayReply {
+    reason: StrayReason,
+    correlation_id: Option<String>,
+    /// Test whose request had the correlation id of the reply, if any.
+    test_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StrayReason {
+    MissingCorrelationId,
+    /// The correlation id does not belong to any request of the suite.
+    UnknownCorrelationId,
+    /// The test has already received a reply.
+    Duplicate,
+    /// The test stopped awaiting its reply before it was received.
+    Late,
+}
+
+impl StrayReply {
+    pub fn new(
+        reason: StrayReason,
+        correlation_id: Option<String>,
+        test_name: Option<String>,
+    ) -> StrayReply {
+        StrayReply {
+            reason,
+            correlation_id,
+            test_name,
+        }
+    }
+}
+
+impl fmt::Display for StrayReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            StrayReason::MissingCorrelationId => "reply without correlation id",
+            StrayReason::UnknownCorrelationId => "reply with unknown correlation id",
+            StrayReason::Duplicate => "duplicate reply",
+            StrayReason::Late => "late reply",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+impl fmt::Display for StrayReply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)?;
+
+        if let Some(correlation_id) = &self.correlation_id {
+            write!(f, " '{}'", correlation_id)?;
+        }
+
+        if let Some(test_name) = &self.test_name {
+            write!(f, " for test '{}'", test_name)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,30 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::{config::amqp::Amqp};
+use crate::config::amqp_binding::AmqpBinding;
+use crate::config::amqp_exchange::AmqpExchange;
+use crate::error::Error;
 use serde::{Deserialize, Serialize};
+use crate::testing::assert_script_runner;
+use crate::testing::request_properties::RequestProperties;
 use crate::testing::test::Test;
+use crate::testing::reply_mode::ReplyMode;
 use crate::testing::run_mode::RunMode;
 use crate::testing::test_type::TestType;
 
+const DEFAULT_REPLY_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_STRAY_REPLY_GRACE_MS: u64 = 500;
+
+fn default_reply_timeout_ms() -> u64 {
+    DEFAULT_REPLY_TIMEOUT_MS
+}
+
+fn default_stray_reply_grace_ms() -> u64 {
+    DEFAULT_STRAY_REPLY_GRACE_MS
+}
+
 /// A container of tests.
 #[derive(Deserialize, Serialize)]
 pub struct Suite {
@@ -15,12 +34,47 @@ pub struct Suite {
     tests: Vec<Test>,
     request_amqp_configuration: Amqp,
     reply_amqp_configuration: Amqp,
+    /// How replies are received, through reply queues unless specified.
+    #[serde(default)]
+    reply_mode: ReplyMode,
+    /// Whether replies which no test was awaiting fail the suite.
+    #[serde(default)]
+    fail_on_stray_replies: bool,
+    /// Time replies are still consumed for once every test has ended, so late and duplicate
+    /// replies to the last tests are reported as stray replies. It is also the time a request
+    /// is remembered for once no longer awaited, after which its replies are deemed unknown.
+    #[serde(default = "default_stray_reply_grace_ms")]
+    stray_reply_grace_ms: u64,
+    /// Exchanges declared before the tests are run.
+    #[serde(default)]
+    exchanges: Vec<AmqpExchange>,
+    /// Bindings declared before the tests are run, once the request queue has been declared.
+    #[serde(default)]
+    bindings: Vec<AmqpBinding>,
+    /// Tags shared by every test of the suite.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Time each test waits for its reply unless the test overrides it.
+    #[serde(default = "default_reply_timeout_ms")]
+    reply_timeout_ms: u64,
+    /// AMQP properties of the requests of every test, unless a test overrides them.
+    #[serde(default)]
+    request_properties: RequestProperties,
+    /// Runner of the assert scripts of the tests, unless a test overrides it.
+    runner: Option<String>,
+    /// Directory of the assert scripts, relative to the suite file.
+    scripts_dir: Option<PathBuf>,
+    /// Long-lived process asserting every reply of the suite, within the scripts directory.
+    assert_worker: Option<String>,
 
+    /// Directory of the file the suite was read from.
+    #[serde(skip)]
+    source_dir: PathBuf,
     #[serde(skip)]
     shared_tests: Vec<Arc<Test>>,
 }
 
-impl<'a> Suite {
+impl Suite {
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
@@ -33,6 +87,18 @@ impl<'a> Suite {
         &self.run_mode
     }
 
+    pub fn reply_mode(&self) -> ReplyMode {
+        self.reply_mode
+    }
+
+    pub fn fail_on_stray_replies(&self) -> bool {
+        self.fail_on_stray_replies
+    }
+
+    pub fn stray_reply_grace(&self) -> Duration {
+        Duration::from_millis(self.stray_reply_grace_ms)
+    }
+
     pub fn test_count(&self) -> usize {
         match self.test_type {
             TestType::Assert => self.tests.len(),
@@ -40,6 +106,75 @@ impl<'a> Suite {
         }
     }
 
+    /// Returns the name of every test as many times as it is going to be run.
+    pub fn test_ids(&self) -> Vec<String> {
+        let times = match self.test_type {
+            TestType::Assert => 1,
+            TestType::Stress { times } => times,
+        };
+
+        let mut test_ids = Vec::<String>::with_capacity(self.test_count());
+
+        for _ in 0..times {
+            for test in &self.tests {
+                test_ids.push(test.name().to_string());
+            }
+        }
+
+        test_ids
+    }
+
+    /// Returns the time the given test waits for its reply.
+    pub fn reply_timeout(&self, test: &Test) -> Duration {
+        Duration::from_millis(test.reply_timeout_ms().unwrap_or(self.reply_timeout_ms))
+    }
+
+    /// Returns the AMQP properties of the given test's request.
+    pub fn request_properties(&self, test: &Test) -> RequestProperties {
+        self.request_properties.overridden_by(test.request_properties())
+    }
+
+    /// Checks that the runner of the suite, if any, names a runner and that the AMQP properties
+    /// shared by the requests of every test can be published.
+    pub fn try_validate(&self) -> Result<(), Error> {
+        if let Some(runner) = &self.runner {
+            assert_script_runner::try_validate_runner(runner)?;
+        }
+
+        self.request_properties.try_validate()
+    }
+
+    pub fn tests(&self) -> &[Test] {
+        self.tests.as_slice()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        self.tags.as_slice()
+    }
+
+    pub fn runner(&self) -> Option<&str> {
+        self.runner.as_deref()
+    }
+
+    pub fn assert_worker(&self) -> Option<&str> {
+        self.assert_worker.as_deref()
+    }
+
+    pub fn set_source_dir(&mut self, source_dir: PathBuf) {
+        self.source_dir = source_dir;
+    }
+
+    /// Returns the directory which the assert scripts of the suite are resolved from:
+    /// its `scripts_dir` if specified, otherwise the suite's folder within the scripts root
+    /// if one is configured, otherwise the directory of the suite file.
+    pub fn scripts_dir(&self, scripts_root: Option<&Path>) -> PathBuf {
+        match (&self.scripts_dir, scripts_root) {
+            (Some(scripts_dir), _) => self.source_dir.join(scripts_dir),
+            (None, Some(scripts_root)) => scripts_root.join(self.name.as_str()),
+            (None, None) => self.source_dir.clone(),
+        }
+    }
+
     pub fn mut_tests(&mut self) -> &mut Vec<Test> {
         &mut self.tests
     }
@@ -63,7 +198,11 @@ impl<'a> Suite {
         &self.reply_amqp_configuration
     }
 
-    pub fn mut_reply_amqp_configuration(&mut self) -> &mut Amqp {
-        &mut self.reply_amqp_configuration
+    pub fn exchanges(&self) -> &[AmqpExchange] {
+        self.exchanges.as_slice()
+    }
+
+    pub fn bindings(&self) -> &[AmqpBinding] {
+        self.bindings.as_slice()
     }
 }
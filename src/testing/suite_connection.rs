@@ -0,0 +1,356 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use lapin::options::{
+    BasicConsumeOptions, ConfirmSelectOptions, ExchangeDeleteOptions, QueueDeleteOptions,
+};
+use lapin::types::FieldTable;
+use lapin::{Channel, Queue};
+use tokio::sync::Mutex;
+
+use crate::amqp_connection_manager::AmqpConnectionManager;
+use crate::config::amqp_binding::AmqpBinding;
+use crate::config::amqp_exchange::AmqpExchange;
+use crate::config::amqp_instance_config;
+use crate::config::amqp_queue::AmqpQueue;
+use crate::error::{Error, ErrorKind};
+use crate::testing::reply_dispatcher::ReplyDispatcher;
+use crate::testing::reply_mode::{ReplyMode, DIRECT_REPLY_TO_QUEUE};
+use crate::testing::stray_reply::StrayReply;
+use crate::testing::suite::Suite;
+
+/// Channel of a suite along with everything the suite declares through it, shared by every test
+/// of the suite so each one publishes its request through a channel which is still open.
+/// The channel is set up again whenever it is lost, so only the tests awaiting their reply
+/// through the lost one are errored.
+pub struct SuiteConnection {
+    amqp_connection_manager: Arc<AmqpConnectionManager>,
+    suite_name: String,
+    reply_mode: ReplyMode,
+    exchanges: Vec<AmqpExchange>,
+    bindings: Vec<AmqpBinding>,
+    request_queue: AmqpQueue,
+    reply_queue: AmqpQueue,
+    consume_options: BasicConsumeOptions,
+    consume_arguments: FieldTable,
+    stray_reply_grace: Duration,
+    state: Mutex<SuiteConnectionState>,
+}
+
+#[derive(Default)]
+struct SuiteConnectionState {
+    /// Channel in use, none until the suite is first connected.
+    suite_channel: Option<SuiteChannel>,
+    /// Stray replies of every reply dispatcher of the suite, including the ones replaced
+    /// after their channel was lost.
+    stray_replies: Vec<StrayReply>,
+}
+
+/// Channel which the requests of a suite are published through, along with the consumer
+/// of their replies.
+#[derive(Clone)]
+pub struct SuiteChannel {
+    reply_dispatcher: Arc<ReplyDispatcher>,
+    /// Name of the request queue as declared through the channel.
+    request_queue_name: String,
+}
+
+impl SuiteChannel {
+    pub fn channel(&self) -> &Channel {
+        self.reply_dispatcher.channel()
+    }
+
+    pub fn reply_dispatcher(&self) -> &ReplyDispatcher {
+        &self.reply_dispatcher
+    }
+
+    pub fn request_queue_name(&self) -> &str {
+        self.request_queue_name.as_str()
+    }
+}
+
+impl SuiteConnection {
+    pub fn try_new(
+        amqp_connection_manager: Arc<AmqpConnectionManager>,
+        test_suite: &Suite,
+    ) -> Result<SuiteConnection, Error> {
+        let amqp_instance_config = amqp_instance_config::try_get_from_request_and_reply_amqp(
+            test_suite.request_amqp_configuration(),
+            test_suite.reply_amqp_configuration(),
+        )?;
+
+        Ok(SuiteConnection {
+            amqp_connection_manager,
+            suite_name: test_suite.name().to_string(),
+            reply_mode: test_suite.reply_mode(),
+            exchanges: test_suite.exchanges().to_vec(),
+            bindings: test_suite.bindings().to_vec(),
+            request_queue: test_suite.request_amqp_configuration().queue().clone(),
+            reply_queue: test_suite.reply_amqp_configuration().queue().clone(),
+            consume_options: *amqp_instance_config.consume_options(),
+            consume_arguments: amqp_instance_config.consume_arguments().clone(),
+            stray_reply_grace: test_suite.stray_reply_grace(),
+            state: Mutex::new(SuiteConnectionState::default()),
+        })
+    }
+
+    /// Returns the channel of the suite, first setting up a new channel along with everything
+    /// the suite declares if there is none yet or the one in use has been lost.
+    pub async fn try_connect(&self) -> Result<SuiteChannel, Error> {
+        let mut state = self.state.lock().await;
+
+        if let Some(suite_channel) = &state.suite_channel {
+            if suite_channel.reply_dispatcher.is_healthy() {
+                return Ok(suite_channel.clone());
+            }
+
+            log::warn!("[{}] amqp channel lost: recovering it", self.suite_name);
+            self.stop_reply_dispatcher(&mut state, Duration::ZERO).await;
+        }
+
+        let amqp_channel = self.amqp_connection_manager.try_get_channel().await?;
+        let channel = amqp_channel.channel();
+
+        // requests are confirmed, so the ones the broker refuses or cannot route
+        // are reported as such instead of timing out.
+        if let Err(error) = channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+        {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("failed to enable publisher confirms: {}", error),
+            ));
+        }
+
+        self.declare_exchanges(channel).await?;
+        let request_queue = self.initialize_request_queue(channel).await?;
+        self.declare_bindings(channel).await?;
+        let reply_queue_name = self.initialize_reply_queue_name(channel).await?;
+
+        // shared by every test, so replies are consumed from a single queue
+        let reply_dispatcher = ReplyDispatcher::try_start(
+            amqp_channel,
+            reply_queue_name,
+            self.consume_options,
+            self.consume_arguments.clone(),
+            self.stray_reply_grace,
+        )
+        .await?;
+
+        let suite_channel = SuiteChannel {
+            reply_dispatcher: Arc::new(reply_dispatcher),
+            request_queue_name: request_queue.name().to_string(),
+        };
+
+        state.suite_channel = Some(suite_channel.clone());
+        Ok(suite_channel)
+    }
+
+    /// Stops consuming replies once the grace period has elapsed and deletes the reply queue,
+    /// returning the replies which no test was awaiting.
+    pub async fn stop(&self, grace: Duration) -> Vec<StrayReply> {
+        let mut state = self.state.lock().await;
+        self.stop_reply_dispatcher(&mut state, grace).await;
+
+        std::mem::take(&mut state.stray_replies)
+    }
+
+    /// Removes the bindings and then the exchanges which are meant to be deleted after the run.
+    /// Failures are only logged, since the tests have already been run by then.
+    pub async fn remove_exchanges_and_bindings(&self) {
+        let has_removals = self
+            .bindings
+            .iter()
+            .any(|binding| binding.delete_after_run())
+            || self
+                .exchanges
+                .iter()
+                .any(|exchange| exchange.delete_after_run());
+
+        if !has_removals {
+            return;
+        }
+
+        let channel = match self.amqp_connection_manager.try_get_channel().await {
+            Ok(amqp_channel) => amqp_channel.channel().clone(),
+            Err(error) => {
+                log::error!(
+                    "failed to get channel to remove exchanges and bindings: {}",
+                    error
+                );
+                return;
+            }
+        };
+
+        for binding in self
+            .bindings
+            .iter()
+            .filter(|binding| binding.delete_after_run())
+        {
+            if let Err(error) = channel
+                .queue_unbind(
+                    binding.queue(),
+                    binding.exchange(),
+                    binding.routing_key(),
+                    binding.bind_arguments().clone(),
+                )
+                .await
+            {
+                log::error!(
+                    "failed to unbind queue '{}' from exchange '{}': {}",
+                    binding.queue(),
+                    binding.exchange(),
+                    error
+                );
+            }
+        }
+
+        for exchange in self
+            .exchanges
+            .iter()
+            .filter(|exchange| exchange.delete_after_run())
+        {
+            if let Err(error) = channel
+                .exchange_delete(exchange.name(), ExchangeDeleteOptions::default())
+                .await
+            {
+                log::error!("failed to delete exchange '{}': {}", exchange.name(), error);
+            }
+        }
+    }
+
+    /// Stops consuming replies once the grace period has elapsed and deletes the reply queue,
+    /// if one was declared and its channel is still open, keeping the replies which no test
+    /// was awaiting.
+    async fn stop_reply_dispatcher(&self, state: &mut SuiteConnectionState, grace: Duration) {
+        let reply_dispatcher = match state.suite_channel.take() {
+            Some(suite_channel) => suite_channel.reply_dispatcher,
+            None => return,
+        };
+
+        state
+            .stray_replies
+            .extend(reply_dispatcher.stop(grace).await);
+
+        if self.reply_mode == ReplyMode::Queue && reply_dispatcher.channel().status().connected() {
+            match reply_dispatcher
+                .channel()
+                .queue_delete(
+                    reply_dispatcher.reply_queue_name(),
+                    QueueDeleteOptions::default(),
+                )
+                .await
+            {
+                Ok(_) => (),
+                Err(error) => log::error!("failed to delete reply queue: {}", error),
+            }
+        }
+    }
+
+    async fn declare_exchanges(&self, channel: &Channel) -> Result<(), Error> {
+        for exchange in self.exchanges.iter() {
+            if let Err(error) = channel
+                .exchange_declare(
+                    exchange.name(),
+                    exchange.kind().clone(),
+                    *exchange.declare_options(),
+                    exchange.declare_arguments().clone(),
+                )
+                .await
+            {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "failed to declare exchange '{}': {}",
+                        exchange.name(),
+                        error
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn declare_bindings(&self, channel: &Channel) -> Result<(), Error> {
+        for binding in self.bindings.iter() {
+            if let Err(error) = channel
+                .queue_bind(
+                    binding.queue(),
+                    binding.exchange(),
+                    binding.routing_key(),
+                    *binding.bind_options(),
+                    binding.bind_arguments().clone(),
+                )
+                .await
+            {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!(
+                        "failed to bind queue '{}' to exchange '{}': {}",
+                        binding.queue(),
+                        binding.exchange(),
+                        error
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn initialize_request_queue(&self, channel: &Channel) -> Result<Queue, Error> {
+        let request_queue = match channel
+            .queue_declare(
+                self.request_queue.name(),
+                *self.request_queue.declare_options(),
+                self.request_queue.declare_arguments().clone(),
+            )
+            .await
+        {
+            Ok(request_queue) => request_queue,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to declare request queue: {}", error),
+                ));
+            }
+        };
+
+        Ok(request_queue)
+    }
+
+    async fn initialize_reply_queue(&self, channel: &Channel) -> Result<Queue, Error> {
+        let reply_queue = match channel
+            .queue_declare(
+                self.reply_queue.name(),
+                *self.reply_queue.declare_options(),
+                self.reply_queue.declare_arguments().clone(),
+            )
+            .await
+        {
+            Ok(reply_queue) => reply_queue,
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to declare response queue: {}", error),
+                ));
+            }
+        };
+
+        Ok(reply_queue)
+    }
+
+    /// Returns the name of the queue which replies are consumed from, declaring it
+    /// unless replies are received through direct reply-to.
+    async fn initialize_reply_queue_name(&self, channel: &Channel) -> Result<String, Error> {
+        match self.reply_mode {
+            ReplyMode::Queue => {
+                let reply_queue = self.initialize_reply_queue(channel).await?;
+                Ok(reply_queue.name().to_string())
+            }
+            ReplyMode::DirectReplyTo => Ok(DIRECT_REPLY_TO_QUEUE.to_string()),
+        }
+    }
+}
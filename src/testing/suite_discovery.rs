@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, ErrorKind};
+
+const SUITE_FILE_EXTENSION: &str = "json";
+const GLOB_SPECIAL_CHARACTERS: [char; 3] = ['*', '?', '['];
+
+/// Resolves the given inputs into a list of suite files.
+/// Each input may be a suite file, a directory which is searched recursively for suite files,
+/// or a glob pattern such as `suites/**/*.json`.
+/// Matches of each input are sorted so the resulting order is stable, and files which have
+/// already been found by a previous input are not repeated.
+pub fn discover(inputs: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::<PathBuf>::new();
+
+    for input in inputs {
+        let mut input_files = if is_glob_pattern(input) {
+            find_by_pattern(input)?
+        } else if Path::new(input).is_dir() {
+            find_in_directory(Path::new(input))?
+        } else {
+            vec![PathBuf::from(input)]
+        };
+
+        if input_files.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("no suite files found for '{}'", input),
+            ));
+        }
+
+        input_files.sort();
+
+        for file in input_files {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn is_glob_pattern(input: &str) -> bool {
+    input.contains(GLOB_SPECIAL_CHARACTERS)
+}
+
+fn find_in_directory(directory: &Path) -> Result<Vec<PathBuf>, Error> {
+    let pattern = directory
+        .join("**")
+        .join(format!("*.{}", SUITE_FILE_EXTENSION));
+
+    find_by_pattern(pattern.to_string_lossy().as_ref())
+}
+
+fn find_by_pattern(pattern: &str) -> Result<Vec<PathBuf>, Error> {
+    let paths = match glob::glob(pattern) {
+        Ok(paths) => paths,
+        Err(error) => {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("invalid suite pattern '{}': {}", pattern, error),
+            ));
+        }
+    };
+
+    let mut files = Vec::<PathBuf>::new();
+
+    for path in paths {
+        match path {
+            Ok(path) => {
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to read suite path: {}", error),
+                ));
+            }
+        }
+    }
+
+    Ok(files)
+}
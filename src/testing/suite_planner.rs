@@ -0,0 +1,123 @@
+use crate::config::amqp_instance_config;
+use crate::config::tester_config::TesterConfig;
+use crate::error::{Error, ErrorKind};
+use crate::testing::assert_script_runner;
+use crate::testing::suite::Suite;
+
+/// Checks that every suite is ready to be run and logs the tests that would be executed,
+/// without authenticating nor connecting to the API.
+/// Every problem found is logged before an error is returned, so all of them can be fixed at once.
+pub fn plan(tester_config: &TesterConfig, suites: &[Suite]) -> Result<(), Error> {
+    let mut problem_count = 0;
+
+    for suite in suites {
+        log::info!(
+            "# suite '{}' ({:?}, {:?}, {:?}): {} test(s) #",
+            suite.name(),
+            suite.run_mode(),
+            suite.test_type(),
+            suite.reply_mode(),
+            suite.test_count()
+        );
+
+        if let Err(error) = amqp_instance_config::try_get_from_request_and_reply_amqp(
+            suite.request_amqp_configuration(),
+            suite.reply_amqp_configuration(),
+        ) {
+            log::error!(
+                "suite '{}' has an invalid amqp configuration: {}",
+                suite.name(),
+                error
+            );
+            problem_count += 1;
+        }
+
+        for exchange in suite.exchanges() {
+            log::info!(
+                "\t-> declares {:?} exchange '{}'",
+                exchange.kind(),
+                exchange.name()
+            );
+        }
+
+        for binding in suite.bindings() {
+            log::info!(
+                "\t-> binds queue '{}' to exchange '{}' with routing key '{}'",
+                binding.queue(),
+                binding.exchange(),
+                binding.routing_key()
+            );
+        }
+
+        let scripts_dir = suite.scripts_dir(tester_config.scripts_root());
+
+        if let Some(assert_worker) = suite.assert_worker() {
+            let worker_path =
+                assert_script_runner::script_path(scripts_dir.as_path(), assert_worker);
+
+            if worker_path.is_file() {
+                log::info!("\t-> asserted by worker '{}'", worker_path.display());
+            } else {
+                log::error!(
+                    "\t-> assertion worker '{}' does not exist",
+                    worker_path.display()
+                );
+                problem_count += 1;
+            }
+        }
+
+        for test in suite.tests() {
+            if !test.expectations().is_empty() {
+                log::info!(
+                    "\t-> test '{}' asserted by {} expectation(s)",
+                    test.name(),
+                    test.expectations().len()
+                );
+            }
+
+            if !test.property_expectations().is_empty() {
+                log::info!(
+                    "\t-> test '{}' asserted by {} property expectation(s)",
+                    test.name(),
+                    test.property_expectations().len()
+                );
+            }
+
+            if test.schema().is_some() {
+                log::info!("\t-> test '{}' validated by a schema", test.name());
+            }
+
+            let assert_script = match test.assert_script() {
+                Some(assert_script) => assert_script,
+                None => continue,
+            };
+
+            let script_path =
+                assert_script_runner::script_path(scripts_dir.as_path(), assert_script);
+
+            if script_path.is_file() {
+                log::info!(
+                    "\t-> test '{}' asserted by '{}'",
+                    test.name(),
+                    script_path.display()
+                );
+            } else {
+                log::error!(
+                    "\t-> test '{}' assertion script '{}' does not exist",
+                    test.name(),
+                    script_path.display()
+                );
+                problem_count += 1;
+            }
+        }
+    }
+
+    if problem_count > 0 {
+        return Err(Error::new(
+            ErrorKind::InternalFailure,
+            format!("found {} problem(s) within the test suites", problem_count),
+        ));
+    }
+
+    Ok(())
+}
@@ -1,18 +1,21 @@
+use std::path::{Path, PathBuf};
+
 use crate::error::{Error, ErrorKind};
 use crate::testing::suite::Suite;
 
 /// Reads a <b>Suite</b> from a file and proceeds to inject the specified token into the suite,
 /// so the tests can authenticate themselves whenever they make requests to the API.
-pub async fn read(files: &[&str], token: &str) -> Result<Vec<Suite>, Error> {
+/// No token is injected if none is given, which is useful when the suites are not going to be run.
+pub async fn read(files: &[PathBuf], token: Option<&str>) -> Result<Vec<Suite>, Error> {
     let mut tests = Vec::<Suite>::with_capacity(files.len());
 
     for file in files {
-        let file_content = match tokio::fs::read(format!("{}", file)).await {
+        let file_content = match tokio::fs::read(file).await {
             Ok(file_content) => file_content,
             Err(error) => {
                 return Err(Error::new(
                     ErrorKind::InternalFailure,
-                    format!("failed to read file: {}", error),
+                    format!("failed to read file '{}': {}", file.display(), error),
                 ));
             }
         };
@@ -22,11 +25,45 @@ pub async fn read(files: &[&str], token: &str) -> Result<Vec<Suite>, Error> {
             Err(error) => {
                 return Err(Error::new(
                     ErrorKind::InternalFailure,
-                    format!("failed to deserialize test: {}", error),
+                    format!("failed to deserialize test '{}': {}", file.display(), error),
                 ));
             }
         };
 
+        // a suite within the current directory has an empty parent, which would turn its scripts
+        // into bare names looked up through the PATH instead of files within that directory.
+        let suite_dir = match file.parent() {
+            Some(suite_dir) if !suite_dir.as_os_str().is_empty() => suite_dir,
+            _ => Path::new("."),
+        };
+        test.set_source_dir(suite_dir.to_path_buf());
+
+        if let Err(error) = test.try_validate() {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("invalid suite '{}': {}", file.display(), error),
+            ));
+        }
+
+        let has_assert_worker = test.assert_worker().is_some();
+
+        for test in test.mut_tests().iter_mut() {
+            if let Err(error) = test.try_validate(suite_dir, has_assert_worker) {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("invalid suite '{}': {}", file.display(), error),
+                ));
+            }
+        }
+
+        let token = match token {
+            Some(token) => token,
+            None => {
+                tests.push(test);
+                continue;
+            }
+        };
+
         for request in test.mut_tests().as_mut_slice() {
             match request.inject_token(token) {
                 Ok(_) => (),
@@ -1,6 +1,12 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use tokio::sync::mpsc::Receiver;
+use tokio_util::sync::CancellationToken;
 
-use crate::testing::test_result::TestResult;
+use crate::error::{Error, ErrorKind};
+use crate::testing::stray_reply::StrayReply;
+use crate::testing::test_result::{TestOutcome, TestResult};
 
 /// Result that is given by a Suite after every test within it has been executed.
 pub struct SuiteResult {
@@ -8,19 +14,40 @@ pub struct SuiteResult {
     test_count: usize,
     results: Vec<TestResult>,
     test_result_receiver: Receiver<TestResult>,
+    /// Amount of results each test is still expected to send.
+    pending_results: HashMap<String, usize>,
+    /// Time taken to run every test of the suite.
+    duration: Duration,
+    /// Replies which no test was awaiting.
+    stray_replies: Vec<StrayReply>,
+    fail_on_stray_replies: bool,
 }
 
 impl SuiteResult {
+    /// Creates the result of a suite which expects a result per given test id,
+    /// so a test id is repeated as many times as the test is run.
     pub fn new(
         name: String,
-        test_count: usize,
+        test_ids: Vec<String>,
         test_result_receiver: Receiver<TestResult>,
+        fail_on_stray_replies: bool,
     ) -> SuiteResult {
+        let test_count = test_ids.len();
+        let mut pending_results = HashMap::<String, usize>::new();
+
+        for test_id in test_ids {
+            *pending_results.entry(test_id).or_insert(0) += 1;
+        }
+
         SuiteResult {
             name,
             test_count,
             results: Vec::with_capacity(test_count),
             test_result_receiver,
+            pending_results,
+            duration: Duration::ZERO,
+            stray_replies: Vec::new(),
+            fail_on_stray_replies,
         }
     }
 
@@ -28,25 +55,96 @@ impl SuiteResult {
         self.name.as_str()
     }
 
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    pub fn stray_replies(&self) -> &[StrayReply] {
+        self.stray_replies.as_slice()
+    }
+
+    pub fn set_stray_replies(&mut self, stray_replies: Vec<StrayReply>) {
+        self.stray_replies = stray_replies;
+    }
+
     /// Returns the results of every test that has been executed.
     pub fn results(&self) -> &[TestResult] {
         self.results.as_slice()
     }
 
     /// Awaits for each test to send their result.
-    pub async fn collect_results(&mut self) {
-        loop {
-            if let Some(result) = self.test_result_receiver.recv().await {
-                self.results.push(result);
+    /// If the run gets cancelled, the results which have already been sent are kept and
+    /// every test which has not sent its result yet is marked as aborted.
+    pub async fn collect_results(&mut self, cancellation_token: &CancellationToken) {
+        while self.results.len() < self.test_count {
+            tokio::select! {
+                biased;
+                result = self.test_result_receiver.recv() => match result {
+                    Some(result) => self.push(result),
+                    None => break,
+                },
+                _ = cancellation_token.cancelled() => break,
             }
+        }
 
-            if self.results.len() == self.test_count {
-                break;
+        self.fail_pending_results(Error::new(
+            ErrorKind::Aborted,
+            "test aborted before finishing",
+        ));
+    }
+
+    /// Keeps the results which have already been sent and marks every test which has not sent
+    /// its result yet as errored, due to the suite being unable to run them.
+    pub fn error_pending_results(&mut self, error: Error) {
+        self.fail_pending_results(Error::new(
+            error.kind(),
+            format!("test suite could not be run: {}", error),
+        ));
+    }
+
+    fn push(&mut self, result: TestResult) {
+        if let Some(pending_results) = self.pending_results.get_mut(result.id()) {
+            *pending_results = pending_results.saturating_sub(1);
+        }
+
+        self.results.push(result);
+    }
+
+    fn fail_pending_results(&mut self, error: Error) {
+        while let Ok(result) = self.test_result_receiver.try_recv() {
+            self.push(result);
+        }
+
+        let mut pending_results = self
+            .pending_results
+            .drain()
+            .collect::<Vec<(String, usize)>>();
+        pending_results.sort();
+
+        for (test_id, count) in pending_results {
+            for _ in 0..count {
+                self.results
+                    .push(TestResult::new(test_id.clone(), Err(error.clone())));
             }
         }
     }
 
-    pub fn has_any_test_failed(&self) -> bool {
+    /// Returns the amount of tests which ended with the given outcome.
+    pub fn count(&self, outcome: TestOutcome) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.outcome() == outcome)
+            .count()
+    }
+
+    /// Whether any test has not passed, or any stray reply has been received
+    /// while the suite fails on them.
+    pub fn has_failed(&self) -> bool {
         self.results.iter().any(|result| result.result().is_err())
+            || (self.fail_on_stray_replies && !self.stray_replies.is_empty())
     }
 }
@@ -1,5 +1,6 @@
 use crate::error::Error;
 use crate::testing::suite_result::SuiteResult;
+use crate::testing::test_result::TestOutcome;
 
 /// Outputs the test suite results into an useful format.
 pub fn output(suite_result: SuiteResult) -> Result<(), Error> {
@@ -8,10 +9,51 @@ pub fn output(suite_result: SuiteResult) -> Result<(), Error> {
 
     for test_result in test_results {
         match test_result.result() {
-            Ok(()) => log::info!("OK   - test '{}'", test_result.id()),
-            Err(error) => log::info!("FAIL - test '{}' : {}", test_result.id(), error),
+            Ok(()) => log::info!("OK    - test '{}'", test_result.id()),
+            Err(error) => match test_result.outcome() {
+                TestOutcome::Aborted => {
+                    log::info!("ABORT - test '{}' : {}", test_result.id(), error)
+                }
+                TestOutcome::Errored => {
+                    log::info!("ERROR - test '{}' : {}", test_result.id(), error)
+                }
+                _ => log::info!("FAIL  - test '{}' : {}", test_result.id(), error),
+            },
         }
+
+        if test_result.result().is_err() {
+            if let Some(script_output) = test_result.script_output() {
+                output_script_stream("stdout", script_output.stdout());
+                output_script_stream("stderr", script_output.stderr());
+            }
+        }
+    }
+
+    for stray_reply in suite_result.stray_replies() {
+        log::info!("STRAY - {}", stray_reply);
     }
 
+    log::info!(
+        "# {} passed, {} failed, {} errored, {} aborted, {} stray in {} ms #",
+        suite_result.count(TestOutcome::Passed),
+        suite_result.count(TestOutcome::Failed),
+        suite_result.count(TestOutcome::Errored),
+        suite_result.count(TestOutcome::Aborted),
+        suite_result.stray_replies().len(),
+        suite_result.duration().as_millis()
+    );
+
     Ok(())
 }
+
+fn output_script_stream(stream_name: &str, stream: &str) {
+    if stream.trim().is_empty() {
+        return;
+    }
+
+    log::info!("\t{}:", stream_name);
+
+    for line in stream.lines() {
+        log::info!("\t| {}", line);
+    }
+}
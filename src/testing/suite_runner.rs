@@ -1,20 +1,22 @@
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::amqp_connection_manager::AmqpConnectionManager;
 use crate::config::amqp_instance_config::{self};
 use crate::error::{Error, ErrorKind};
-use futures_util::stream::FuturesUnordered;
+use futures_util::stream::{FuturesUnordered, StreamExt};
 use futures_util::Future;
-use lapin::options::QueueDeleteOptions;
-use lapin::{Channel, Queue};
 use tokio::sync::mpsc::Sender;
-use crate::config::amqp_queue::AmqpQueue;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use crate::config::tester_config::TesterConfig;
 use crate::testing::assert_script_runner::AssertScriptRunner;
 use crate::testing::test_result::TestResult;
 use crate::testing::run_instance::RunInstance;
 use crate::testing::run_mode::RunMode;
 use crate::testing::suite::Suite;
+use crate::testing::suite_connection::SuiteConnection;
 use crate::testing::suite_result::SuiteResult;
 use crate::testing::test_type::TestType;
 
@@ -22,158 +24,212 @@ use crate::testing::test_type::TestType;
 pub struct SuiteRunner {
     amqp_connection_manager: Arc<AmqpConnectionManager>,
     test_suite_result_sender: Sender<SuiteResult>,
-    test_tasks: FuturesUnordered<Pin<Box<dyn Future<Output = ()> + Send + Sync>>>,
+    tester_config: Arc<TesterConfig>,
+    test_tasks: FuturesUnordered<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    spawned_test_tasks: Vec<JoinHandle<()>>,
     stress_mode: bool,
+    cancellation_token: CancellationToken,
+    /// Channel of the suite being executed, which every test resolves before publishing its
+    /// request and which is stopped once every test has ended.
+    suite_connection: Option<Arc<SuiteConnection>>,
+    /// Runner of the assertions of the suite being executed, whose assertion worker
+    /// is stopped once every test has ended.
+    assert_script_runner: Option<Arc<AssertScriptRunner>>,
 }
 
 impl SuiteRunner {
     pub fn new(
         amqp_connection_manager: Arc<AmqpConnectionManager>,
         test_suite_result_sender: Sender<SuiteResult>,
+        tester_config: Arc<TesterConfig>,
+        cancellation_token: CancellationToken,
     ) -> SuiteRunner {
         SuiteRunner {
             amqp_connection_manager,
             test_suite_result_sender,
+            tester_config,
             test_tasks: FuturesUnordered::new(),
+            spawned_test_tasks: Vec::new(),
             stress_mode: false,
+            cancellation_token,
+            suite_connection: None,
+            assert_script_runner: None,
         }
     }
 
     /// Executes the given test suite and then proceeds to send a SuiteResult through the result sender.
-    /// Error is returned in case of runtime errors instead of test related ones.
+    /// Runtime errors which prevent tests from being run are reported as the result of those tests,
+    /// so every test always has a result.
+    /// Error is returned only if the SuiteResult cannot be sent.
     pub async fn execute(&mut self, mut test_suite: Suite) -> Result<(), Error> {
-        let channel = self.amqp_connection_manager.try_get_channel().await?;
+        let (result_sender, result_receiver) = tokio::sync::mpsc::channel(4096);
 
-        let request_queue = self.initialize_request_queue(&test_suite, &channel).await?;
+        let mut test_suite_result = SuiteResult::new(
+            test_suite.name().to_string(),
+            test_suite.test_ids(),
+            result_receiver,
+            test_suite.fail_on_stray_replies(),
+        );
 
-        let test_type = test_suite.test_type();
+        let started_at = Instant::now();
 
-        let (result_sender, result_receiver) = tokio::sync::mpsc::channel(4096);
+        match self.run_suite(&mut test_suite, &result_sender).await {
+            Ok(()) => {
+                test_suite_result
+                    .collect_results(&self.cancellation_token)
+                    .await
+            }
+            Err(error) => {
+                log::error!("failed to run test suite '{}': {}", test_suite.name(), error);
+
+                // every test which has been started is awaited, so the remaining ones
+                // are known to have never been run.
+                self.await_spawned_test_tasks().await;
+                test_suite_result.error_pending_results(error);
+            }
+        }
+
+        self.await_spawned_test_tasks().await;
+
+        if let Some(assert_script_runner) = self.assert_script_runner.take() {
+            assert_script_runner.stop_assert_worker().await;
+        }
+
+        test_suite_result.set_duration(started_at.elapsed());
+
+        // replies are not awaited any longer once the run has been cancelled
+        let stray_reply_grace = if self.cancellation_token.is_cancelled() {
+            Duration::ZERO
+        } else {
+            test_suite.stray_reply_grace()
+        };
+
+        if let Some(suite_connection) = self.suite_connection.take() {
+            test_suite_result.set_stray_replies(suite_connection.stop(stray_reply_grace).await);
+            suite_connection.remove_exchanges_and_bindings().await;
+        }
+
+        match self.test_suite_result_sender.send(test_suite_result).await {
+            Ok(_) => {}
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("failed to send test suite result: {}", error),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_suite(
+        &mut self,
+        test_suite: &mut Suite,
+        result_sender: &Sender<TestResult>,
+    ) -> Result<(), Error> {
+        let suite_connection = Arc::new(SuiteConnection::try_new(
+            self.amqp_connection_manager.clone(),
+            test_suite,
+        )?);
+        self.suite_connection = Some(suite_connection.clone());
+
+        // connected up front, so a suite which cannot be set up does not run any test
+        suite_connection.try_connect().await?;
+
+        // shared by every run, so a suite's assertion worker is started only once
+        let assert_script_runner = Arc::new(self.try_create_assert_script_runner(test_suite)?);
+        self.assert_script_runner = Some(assert_script_runner.clone());
+
+        let test_type = test_suite.test_type();
 
         match test_type {
             TestType::Assert => {
                 self.run(
-                    &mut test_suite,
-                    &request_queue,
-                    &channel,
-                    &result_sender,
+                    test_suite,
+                    &suite_connection,
+                    &assert_script_runner,
+                    result_sender,
                 )
                 .await?
             }
             TestType::Stress { times } => {
                 self.stress_mode = true;
+                let mut run_error = None;
 
                 for time in 0..times {
                     match self
                         .run(
-                            &mut test_suite,
-                            &request_queue,
-                            &channel,
-                            &result_sender,
+                            test_suite,
+                            &suite_connection,
+                            &assert_script_runner,
+                            result_sender,
                         )
                         .await
                     {
                         Ok(_) => log::info!("run finished successfully #{}", time),
-                        Err(error) => log::error!("run failed #{} : {}", time, error),
+                        Err(error) => {
+                            log::error!("run failed #{} : {}", time, error);
+                            run_error.get_or_insert(error);
+                        }
                     }
                 }
 
-                for future in self.test_tasks.iter_mut() {
-                    future.await;
-                }
-            }
-        }
-
-        let mut test_suite_result = SuiteResult::new(
-            test_suite.name().to_string(),
-            test_suite.test_count(),
-            result_receiver,
-        );
-
-        test_suite_result.collect_results().await;
+                // polled together, so the tests of every run await their reply concurrently
+                while self.test_tasks.next().await.is_some() {}
 
-        match self.test_suite_result_sender.send(test_suite_result).await {
-            Ok(_) => {}
-            Err(error) => {
-                return Err(Error::new(
-                    ErrorKind::InternalFailure,
-                    format!("failed to send test suite result: {}", error),
-                ));
+                if let Some(run_error) = run_error {
+                    return Err(run_error);
+                }
             }
         }
 
         Ok(())
     }
 
-    async fn initialize_request_queue(
-        &self,
-        test: &Suite,
-        channel: &Channel,
-    ) -> Result<Queue, Error> {
-        let request_queue_config = test.request_amqp_configuration().queue();
-
-        let request_queue = match channel
-            .queue_declare(
-                request_queue_config.name(),
-                request_queue_config.declare_options().clone(),
-                request_queue_config.declare_arguments().clone(),
-            )
-            .await
-        {
-            Ok(request_queue) => request_queue,
-            Err(error) => {
-                return Err(Error::new(
-                    ErrorKind::InternalFailure,
-                    format!("failed to declare request queue: {}", error),
-                ));
+    /// Spawned tests are awaited, so the reply queue is only deleted once none of them
+    /// awaits a reply anymore.
+    async fn await_spawned_test_tasks(&mut self) {
+        for spawned_test_task in self.spawned_test_tasks.drain(..) {
+            if let Err(error) = spawned_test_task.await {
+                log::error!("failed to await test task: {}", error);
             }
-        };
-
-        Ok(request_queue)
+        }
     }
 
-    async fn initialize_reply_queue(
+    fn try_create_assert_script_runner(
         &self,
-        reply_queue_config: &AmqpQueue,
-        channel: &Channel,
-    ) -> Result<Queue, Error> {
-        let reply_queue = match channel
-            .queue_declare(
-                reply_queue_config.name(),
-                reply_queue_config.declare_options().clone(),
-                reply_queue_config.declare_arguments().clone(),
-            )
-            .await
-        {
-            Ok(reply_queue) => reply_queue,
-            Err(error) => {
-                return Err(Error::new(
-                    ErrorKind::InternalFailure,
-                    format!("failed to declare response queue: {}", error),
-                ));
-            }
-        };
-
-        Ok(reply_queue)
+        test_suite: &Suite,
+    ) -> Result<AssertScriptRunner, Error> {
+        AssertScriptRunner::try_new(test_suite, &self.tester_config)
     }
 
     async fn run(
         &mut self,
         test_suite: &mut Suite,
-        request_queue: &Queue,
-        channel: &Channel,
+        suite_connection: &Arc<SuiteConnection>,
+        assert_script_runner: &Arc<AssertScriptRunner>,
         result_sender: &Sender<TestResult>,
     ) -> Result<(), Error> {
         let mode = test_suite.run_mode();
 
         match mode {
             RunMode::Sequential => {
-                self.run_sequentially(test_suite, &request_queue, channel, &result_sender)
-                    .await?;
+                self.run_sequentially(
+                    test_suite,
+                    suite_connection,
+                    assert_script_runner,
+                    result_sender,
+                )
+                .await?;
             }
             RunMode::Parallel => {
-                self.run_parallelly(test_suite, &request_queue, &result_sender)
-                    .await?;
+                self.run_parallelly(
+                    test_suite,
+                    suite_connection,
+                    assert_script_runner,
+                    result_sender,
+                )
+                .await?;
             }
         }
 
@@ -181,43 +237,36 @@ impl SuiteRunner {
     }
 
     async fn run_sequentially(
-        &self,
+        &mut self,
         test_suite: &mut Suite,
-        request_queue: &Queue,
-        channel: &Channel,
+        suite_connection: &Arc<SuiteConnection>,
+        assert_script_runner: &Arc<AssertScriptRunner>,
         result_sender: &Sender<TestResult>,
     ) -> Result<(), Error> {
         let amqp_instance_config = amqp_instance_config::try_get_from_request_and_reply_amqp(
             test_suite.request_amqp_configuration(),
             test_suite.reply_amqp_configuration(),
         )?;
-        let reply_queue = self.initialize_reply_queue(test_suite.reply_amqp_configuration().queue(), &channel).await?;
-
-        let test_suite_name = Arc::new(test_suite.name().to_string());
-        let tests = test_suite.shared_tests();
 
-        let assert_script_runner = Arc::new(AssertScriptRunner::try_new(test_suite_name)?);
+        let tests = test_suite.shared_tests().to_vec();
 
-        for test in tests {
+        for test in &tests {
             let test_run_instance = RunInstance::new(
                 test.clone(),
-                channel.clone(),
-                request_queue.name().to_string(),
-                reply_queue.name().to_string(),
+                suite_connection.clone(),
                 amqp_instance_config.clone(),
                 result_sender.clone(),
-                assert_script_runner.clone()
+                assert_script_runner.clone(),
+                self.cancellation_token.clone(),
+                test_suite.reply_timeout(test),
+                test_suite.request_properties(test),
             );
 
             test_run_instance.run().await?;
-        }
 
-        match channel
-            .queue_delete(reply_queue.name().as_str(), QueueDeleteOptions::default())
-            .await
-        {
-            Ok(_) => (),
-            Err(error) => log::error!("failed to delete reply queue: {}", error),
+            if self.cancellation_token.is_cancelled() {
+                break;
+            }
         }
 
         Ok(())
@@ -226,53 +275,40 @@ impl SuiteRunner {
     async fn run_parallelly(
         &mut self,
         test_suite: &mut Suite,
-        request_queue: &Queue,
+        suite_connection: &Arc<SuiteConnection>,
+        assert_script_runner: &Arc<AssertScriptRunner>,
         result_sender: &Sender<TestResult>,
     ) -> Result<(), Error> {
         let amqp_instance_config = amqp_instance_config::try_get_from_request_and_reply_amqp(
             test_suite.request_amqp_configuration(),
             test_suite.reply_amqp_configuration(),
         )?;
-        let reply_queue_config = test_suite.reply_amqp_configuration().queue().clone();
 
         let test_suite_name = Arc::new(test_suite.name().to_string());
-        let tests = test_suite.shared_tests();
+        let tests = test_suite.shared_tests().to_vec();
 
-        let assert_script_runner = Arc::new(AssertScriptRunner::try_new(test_suite_name.clone())?);
-
-        for test in tests {
+        for test in &tests {
             let test_suite_name_clone = test_suite_name.clone();
             let test_name = test.name().to_string();
-            let channel = self.amqp_connection_manager.try_get_channel().await?;
-
-            // reply queues must be initialized per request due to them being treated parallelly,
-            // otherwise a racing condition pops up, since response deliveries may be consumed
-            // in any order.
-            let reply_queue = self.initialize_reply_queue(&reply_queue_config, &channel).await?;
 
+            // every test shares the reply queue, since replies are handed to each test
+            // by their correlation id, no matter the order they are consumed in.
+            // The channel is resolved once the test runs, so a lost one is only used
+            // by the tests already awaiting their reply through it.
             let test_run_instance = RunInstance::new(
                 test.clone(),
-                channel.clone(),
-                request_queue.name().to_string(),
-                reply_queue.name().to_string(),
+                suite_connection.clone(),
                 amqp_instance_config.clone(),
                 result_sender.clone(),
-                assert_script_runner.clone()
+                assert_script_runner.clone(),
+                self.cancellation_token.clone(),
+                test_suite.reply_timeout(test),
+                test_suite.request_properties(test),
             );
 
             let instance_execution = async move {
                 match test_run_instance.run().await {
-                    Ok(_) => {
-                        log::info!("test '{}' run instance finished", test_name);
-
-                        match channel
-                            .queue_delete(reply_queue.name().as_str(), QueueDeleteOptions::default())
-                            .await
-                        {
-                            Ok(_) => (),
-                            Err(error) => log::error!("failed to delete reply queue: {}", error),
-                        }
-                    },
+                    Ok(_) => log::info!("test '{}' run instance finished", test_name),
                     Err(error) => {
                         log::error!(
                             "[{}] test '{}' run instance failed: {}",
@@ -287,7 +323,11 @@ impl SuiteRunner {
             if self.stress_mode {
                 self.test_tasks.push(Box::pin(instance_execution));
             } else {
-                tokio::spawn(instance_execution);
+                self.spawned_test_tasks.push(tokio::spawn(instance_execution));
+            }
+
+            if self.cancellation_token.is_cancelled() {
+                break;
             }
         }
 
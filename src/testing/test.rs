@@ -1,7 +1,13 @@
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
 use crate::error::{Error, ErrorKind};
+use crate::testing::assert_script_runner::{self, ScriptInput};
+use crate::testing::expectation::Expectation;
+use crate::testing::reply_schema::ReplySchema;
+use crate::testing::request_properties::RequestProperties;
 
 const REQUEST_HEADER: &str = "header";
 const REQUEST_HEADER_TOKEN: &str = "token";
@@ -11,7 +17,31 @@ const REQUEST_HEADER_TOKEN: &str = "token";
 pub struct Test {
     name: String,
     request: Map<String, Value>,
-    assert_script: String,
+    /// Overrides the AMQP properties of the suite for the request.
+    #[serde(default)]
+    request_properties: RequestProperties,
+    /// Script run against the reply, for checks that cannot be expressed as expectations.
+    assert_script: Option<String>,
+    /// Overrides the runner of the suite for the assert script.
+    runner: Option<String>,
+    /// How the reply is given to the assert script.
+    #[serde(default)]
+    script_input: ScriptInput,
+    /// Overrides the configured assert script timeout for this test.
+    script_timeout_ms: Option<u64>,
+    /// Checks evaluated in-process against the JSON reply.
+    #[serde(default)]
+    expect: Vec<Expectation>,
+    /// Checks evaluated against the AMQP properties of the reply, such as `/content_type`
+    /// or `/headers/<name>`.
+    #[serde(default)]
+    expect_properties: Vec<Expectation>,
+    /// JSON Schema which the reply has to match.
+    schema: Option<ReplySchema>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Overrides the reply timeout of the suite for this test.
+    reply_timeout_ms: Option<u64>,
 }
 
 impl Test {
@@ -23,6 +53,10 @@ impl Test {
         &self.request
     }
 
+    pub fn request_properties(&self) -> &RequestProperties {
+        &self.request_properties
+    }
+
     /// Injects the token into the request's header.
     pub fn inject_token(&mut self, token: &str) -> Result<(), Error> {
         let header = match self.request.get_mut(REQUEST_HEADER) {
@@ -31,14 +65,14 @@ impl Test {
                 None => {
                     return Err(Error::new(
                         ErrorKind::InternalFailure,
-                        format!("failed to get header as object"),
+                        "failed to get header as object".to_string(),
                     ));
                 }
             },
             None => {
                 return Err(Error::new(
                     ErrorKind::InternalFailure,
-                    format!("failed to get header"),
+                    "failed to get header".to_string(),
                 ));
             }
         };
@@ -55,7 +89,95 @@ impl Test {
         Ok(())
     }
 
-    pub fn assert_script(&self) -> &str {
-        self.assert_script.as_str()
+    pub fn assert_script(&self) -> Option<&str> {
+        self.assert_script.as_deref()
+    }
+
+    pub fn runner(&self) -> Option<&str> {
+        self.runner.as_deref()
+    }
+
+    pub fn script_input(&self) -> ScriptInput {
+        self.script_input
+    }
+
+    pub fn script_timeout_ms(&self) -> Option<u64> {
+        self.script_timeout_ms
+    }
+
+    pub fn expectations(&self) -> &[Expectation] {
+        self.expect.as_slice()
+    }
+
+    pub fn property_expectations(&self) -> &[Expectation] {
+        self.expect_properties.as_slice()
+    }
+
+    pub fn schema(&self) -> Option<&ReplySchema> {
+        self.schema.as_ref()
+    }
+
+    /// Checks that the test asserts its reply somehow and that its runner, request properties
+    /// and expectations are well formed.
+    /// The reply schema, if any, is read relative to the directory of the suite file.
+    pub fn try_validate(&mut self, suite_dir: &Path, has_assert_worker: bool) -> Result<(), Error> {
+        if self.assert_script.is_none()
+            && self.expect.is_empty()
+            && self.expect_properties.is_empty()
+            && self.schema.is_none()
+            && !has_assert_worker
+        {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!(
+                    "test '{}' has neither an assert script, expectations, property expectations nor a schema",
+                    self.name
+                ),
+            ));
+        }
+
+        if let Some(runner) = &self.runner {
+            if let Err(error) = assert_script_runner::try_validate_runner(runner) {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("test '{}' is invalid: {}", self.name, error),
+                ));
+            }
+        }
+
+        if let Err(error) = self.request_properties.try_validate() {
+            return Err(Error::new(
+                ErrorKind::InternalFailure,
+                format!("test '{}' is invalid: {}", self.name, error),
+            ));
+        }
+
+        for expectation in self.expect.iter().chain(self.expect_properties.iter()) {
+            if let Err(error) = expectation.try_validate() {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("test '{}' is invalid: {}", self.name, error),
+                ));
+            }
+        }
+
+        if let Some(schema) = self.schema.as_mut() {
+            if let Err(error) = schema.try_resolve(suite_dir) {
+                return Err(Error::new(
+                    ErrorKind::InternalFailure,
+                    format!("test '{}' is invalid: {}", self.name, error),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn tags(&self) -> &[String] {
+        self.tags.as_slice()
+    }
+
+    pub fn reply_timeout_ms(&self) -> Option<u64> {
+        self.reply_timeout_ms
     }
 }
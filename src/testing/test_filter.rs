@@ -0,0 +1,179 @@
+use glob::Pattern;
+
+use crate::error::{Error, ErrorKind};
+use crate::testing::suite::Suite;
+use crate::testing::test::Test;
+
+const EXCLUDED_TAG_PREFIX: char = '!';
+
+/// Selects which tests are run depending on their names and tags.
+pub struct TestFilter {
+    filters: Vec<Pattern>,
+    skips: Vec<Pattern>,
+    required_tags: Vec<String>,
+    excluded_tags: Vec<String>,
+}
+
+impl TestFilter {
+    pub fn try_new(
+        filters: &[String],
+        skips: &[String],
+        tags: &[String],
+    ) -> Result<TestFilter, Error> {
+        let mut required_tags = Vec::<String>::new();
+        let mut excluded_tags = Vec::<String>::new();
+
+        for tag in tags {
+            let tag = tag.trim();
+
+            if tag.is_empty() {
+                continue;
+            }
+
+            match tag.strip_prefix(EXCLUDED_TAG_PREFIX) {
+                Some(excluded_tag) => excluded_tags.push(excluded_tag.to_string()),
+                None => required_tags.push(tag.to_string()),
+            }
+        }
+
+        Ok(TestFilter {
+            filters: TestFilter::try_compile_patterns(filters)?,
+            skips: TestFilter::try_compile_patterns(skips)?,
+            required_tags,
+            excluded_tags,
+        })
+    }
+
+    fn try_compile_patterns(patterns: &[String]) -> Result<Vec<Pattern>, Error> {
+        let mut compiled_patterns = Vec::<Pattern>::with_capacity(patterns.len());
+
+        for pattern in patterns {
+            match Pattern::new(pattern) {
+                Ok(compiled_pattern) => compiled_patterns.push(compiled_pattern),
+                Err(error) => {
+                    return Err(Error::new(
+                        ErrorKind::InternalFailure,
+                        format!("invalid name pattern '{}': {}", pattern, error),
+                    ));
+                }
+            }
+        }
+
+        Ok(compiled_patterns)
+    }
+
+    /// Removes the tests which are not selected by the filter from every suite,
+    /// dropping the suites which end up without tests.
+    pub fn apply(&self, suites: Vec<Suite>) -> Vec<Suite> {
+        let mut filtered_suites = Vec::<Suite>::with_capacity(suites.len());
+
+        for mut suite in suites {
+            let suite_name = suite.name().to_string();
+            let suite_tags = suite.tags().to_vec();
+
+            suite
+                .mut_tests()
+                .retain(|test| self.is_selected(suite_name.as_str(), suite_tags.as_slice(), test));
+
+            if suite.tests().is_empty() {
+                log::info!("suite '{}' skipped: no test selected", suite_name);
+                continue;
+            }
+
+            filtered_suites.push(suite);
+        }
+
+        filtered_suites
+    }
+
+    fn is_selected(&self, suite_name: &str, suite_tags: &[String], test: &Test) -> bool {
+        let qualified_name = format!("{}/{}", suite_name, test.name());
+        let matches = |pattern: &Pattern| {
+            pattern.matches(suite_name)
+                || pattern.matches(test.name())
+                || pattern.matches(qualified_name.as_str())
+        };
+
+        if !self.filters.is_empty() && !self.filters.iter().any(matches) {
+            return false;
+        }
+
+        if self.skips.iter().any(matches) {
+            return false;
+        }
+
+        let has_tag = |tag: &String| suite_tags.contains(tag) || test.tags().contains(tag);
+
+        if self.excluded_tags.iter().any(has_tag) {
+            return false;
+        }
+
+        self.required_tags.is_empty() || self.required_tags.iter().any(has_tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn test(name: &str, tags: &[&str]) -> Test {
+        serde_json::from_value(json!({ "name": name, "request": {}, "tags": tags })).unwrap()
+    }
+
+    fn filter(filters: &[&str], skips: &[&str], tags: &[&str]) -> TestFilter {
+        let to_strings = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        TestFilter::try_new(&to_strings(filters), &to_strings(skips), &to_strings(tags)).unwrap()
+    }
+
+    #[test]
+    fn selects_every_test_without_criteria() {
+        assert!(filter(&[], &[], &[]).is_selected("suite", &[], &test("create", &[])));
+    }
+
+    #[test]
+    fn matches_filters_against_suite_test_and_qualified_names() {
+        let create = test("create", &[]);
+
+        assert!(filter(&["suite"], &[], &[]).is_selected("suite", &[], &create));
+        assert!(filter(&["cr*"], &[], &[]).is_selected("suite", &[], &create));
+        assert!(filter(&["suite/create"], &[], &[]).is_selected("suite", &[], &create));
+        assert!(!filter(&["suite/delete"], &[], &[]).is_selected("suite", &[], &create));
+    }
+
+    #[test]
+    fn skips_override_filters() {
+        let test = test("create", &[]);
+
+        assert!(!filter(&["suite"], &["*/create"], &[]).is_selected("suite", &[], &test));
+    }
+
+    #[test]
+    fn requires_any_tag_of_the_suite_or_the_test() {
+        let smoke_test = test("create", &["smoke"]);
+        let suite_tags = vec!["slow".to_string()];
+
+        assert!(filter(&[], &[], &["smoke", "fast"]).is_selected("suite", &[], &smoke_test));
+        assert!(filter(&[], &[], &["slow"]).is_selected("suite", &suite_tags, &smoke_test));
+        assert!(!filter(&[], &[], &["fast"]).is_selected("suite", &[], &smoke_test));
+    }
+
+    #[test]
+    fn excluded_tags_override_required_ones() {
+        let test = test("create", &["smoke", "flaky"]);
+
+        assert!(!filter(&[], &[], &["smoke", "!flaky"]).is_selected("suite", &[], &test));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(TestFilter::try_new(&["[".to_string()], &[], &[]).is_err());
+    }
+}
@@ -1,13 +1,38 @@
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
+use crate::testing::assert_script_runner::ScriptOutput;
+
+/// Final state of a test once its result has been given.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TestOutcome {
+    /// The reply satisfied every assertion.
+    Passed,
+    /// The reply did not satisfy an assertion.
+    Failed,
+    /// The test could not be completed due to an infrastructure or internal failure.
+    Errored,
+    /// The run was interrupted before the test could finish.
+    Aborted,
+}
 
 pub struct TestResult {
     id: String,
     result: Result<(), Error>,
+    script_output: Option<ScriptOutput>,
 }
 
 impl TestResult {
     pub fn new(id: String, result: Result<(), Error>) -> TestResult {
-        TestResult { id, result }
+        TestResult {
+            id,
+            result,
+            script_output: None,
+        }
+    }
+
+    /// Attaches the output of the assert script which produced the result.
+    pub fn with_script_output(mut self, script_output: Option<ScriptOutput>) -> TestResult {
+        self.script_output = script_output;
+        self
     }
 
     pub fn id(&self) -> &str {
@@ -17,4 +42,23 @@ impl TestResult {
     pub fn result(&self) -> &Result<(), Error> {
         &self.result
     }
+
+    pub fn script_output(&self) -> Option<&ScriptOutput> {
+        self.script_output.as_ref()
+    }
+
+    pub fn outcome(&self) -> TestOutcome {
+        match &self.result {
+            Ok(()) => TestOutcome::Passed,
+            Err(error) => match error.kind() {
+                ErrorKind::TestAssertFailure
+                | ErrorKind::ReplyTimeout
+                | ErrorKind::ScriptTimeout => TestOutcome::Failed,
+                ErrorKind::Aborted => TestOutcome::Aborted,
+                ErrorKind::ApiConnectionFailure
+                | ErrorKind::InternalFailure
+                | ErrorKind::RequestRejected => TestOutcome::Errored,
+            },
+        }
+    }
 }
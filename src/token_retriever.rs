@@ -42,14 +42,14 @@ pub async fn try_get_token(
                 None => {
                     return Err(Error::new(
                         ErrorKind::InternalFailure,
-                        format!("failed to deserialize token as string"),
+                        "failed to deserialize token as string".to_string(),
                     ));
                 }
             },
             None => {
                 return Err(Error::new(
                     ErrorKind::InternalFailure,
-                    format!("failed to get token from response"),
+                    "failed to get token from response".to_string(),
                 ));
             }
         },